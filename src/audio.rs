@@ -3,21 +3,25 @@ use bevy_kira_audio::{
     AudioApp, AudioChannel, AudioControl, AudioPlugin, AudioSource, DynamicAudioChannel,
 };
 
-use crate::{GameState, Level};
+use crate::{
+    level::{CurrentLevel, LevelManifest},
+    map::{asset::MapAsset, CurrentMap},
+    GameState,
+};
 
 #[derive(Resource, Component, Default, Clone)]
 struct BackgroundChannel;
 #[derive(Resource, Component, Default, Clone)]
 pub struct SoundEffectChannel;
 
+pub struct LevelAudio {
+    music: Handle<AudioSource>,
+    game_over: Handle<AudioSource>,
+}
+
 #[derive(Resource)]
 pub struct AudioState {
-    level_1: Handle<AudioSource>,
-    level_2: Handle<AudioSource>,
-    level_3: Handle<AudioSource>,
-    game_over_1: Handle<AudioSource>,
-    game_over_2: Handle<AudioSource>,
-    game_over_3: Handle<AudioSource>,
+    levels: Vec<LevelAudio>,
     menu: Handle<AudioSource>,
     volume: f64,
 }
@@ -51,7 +55,7 @@ impl Plugin for GameAudioPlugin {
     }
 }
 
-/// `load_audio` loads the audio file `level1.ogg` from the `audios/background` folder and stores it in
+/// `load_audio` loads the music and game over audio of every level in the manifest and stores them in
 /// the `AudioState` resource
 ///
 /// Arguments:
@@ -59,23 +63,25 @@ impl Plugin for GameAudioPlugin {
 /// * `commands`: Commands - This is the command buffer that we will use to insert the resource into the
 /// world.
 /// * `asset_server`: Res<AssetServer> - This is the asset server that we'll use to load the audio file.
-fn load_audio(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let level_1: Handle<AudioSource> = asset_server.load("audios/background/level_1.ogg");
-    let level_2: Handle<AudioSource> = asset_server.load("audios/background/level_2.ogg");
-    let level_3: Handle<AudioSource> = asset_server.load("audios/background/level_3.ogg");
+/// * `manifest`: Res<LevelManifest> - The levels whose audio will be loaded
+fn load_audio(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    manifest: Res<LevelManifest>,
+) {
+    let levels = manifest
+        .levels
+        .iter()
+        .map(|level| LevelAudio {
+            music: asset_server.load(level.music.as_str()),
+            game_over: asset_server.load(level.game_over.as_str()),
+        })
+        .collect();
     let menu: Handle<AudioSource> = asset_server.load("audios/background/menu.ogg");
-    let game_over_1: Handle<AudioSource> = asset_server.load("audios/sfx/game_over_1.ogg");
-    let game_over_2: Handle<AudioSource> = asset_server.load("audios/sfx/game_over_2.ogg");
-    let game_over_3: Handle<AudioSource> = asset_server.load("audios/sfx/game_over_3.ogg");
 
     commands.insert_resource(AudioState {
-        level_1,
-        level_2,
-        level_3,
+        levels,
         menu,
-        game_over_1,
-        game_over_2,
-        game_over_3,
         volume: 0.1,
     });
 }
@@ -104,39 +110,34 @@ pub fn play_sfx(channel: &DynamicAudioChannel, asset_server: AssetServer, path:
 ///
 /// * `channel`: The audio channel to play the music on.
 /// * `audio_handles`: This is the resource that contains the audio handles.
+/// * `maps`: Res<Assets<MapAsset>>
+/// * `current_map`: Res<CurrentMap> - A map header can replace the music of the level
 fn start_bg_music<T: Component + Default>(
     channel: Res<AudioChannel<T>>,
     audio_handles: Res<AudioState>,
-    level_state: Res<State<Level>>,
+    current_level: Res<CurrentLevel>,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
 ) {
     channel.set_volume(audio_handles.volume);
 
-    //TODO: Improve this level comparison
-    if level_state.current().to_owned() == Level::Level1 {
-        channel.play(audio_handles.level_1.clone());
+    if let Some(music) = current_map.meta(&maps).music {
+        channel.play(asset_server.load(music.as_str()));
+        return;
     }
-    if level_state.current().to_owned() == Level::Level2 {
-        channel.play(audio_handles.level_2.clone());
-    }
-    if level_state.current().to_owned() == Level::Level3 {
-        channel.play(audio_handles.level_3.clone());
+    if let Some(level_audio) = audio_handles.levels.get(current_level.0) {
+        channel.play(level_audio.music.clone());
     }
 }
 
 fn play_game_over<T: Component + Default>(
     channel: Res<AudioChannel<T>>,
     audio_handles: Res<AudioState>,
-    level_state: Res<State<Level>>,
+    current_level: Res<CurrentLevel>,
 ) {
-    //TODO: Improve this level comparison
-    if level_state.current().to_owned() == Level::Level1 {
-        channel.play(audio_handles.game_over_1.clone());
-    }
-    if level_state.current().to_owned() == Level::Level2 {
-        channel.play(audio_handles.game_over_2.clone());
-    }
-    if level_state.current().to_owned() == Level::Level3 {
-        channel.play(audio_handles.game_over_3.clone());
+    if let Some(level_audio) = audio_handles.levels.get(current_level.0) {
+        channel.play(level_audio.game_over.clone());
     }
 }
 
@@ -0,0 +1,83 @@
+//! Validates the maps under `assets/maps`, or the map files given as arguments.
+//!
+//! ```sh
+//! cargo run --bin mapcheck
+//! cargo run --bin mapcheck assets/maps/level3.txt
+//! ```
+//!
+//! Exits with a non-zero code if any map has errors.
+
+use std::{env, fs, process};
+
+use minigame2::{
+    constants::{ASSETS_FOLDER, LEVEL_MANIFEST_PATH},
+    level::LevelManifest,
+    map::{
+        asset::{list_map_files, MapAsset},
+        palette::load_level_palette,
+        validate::{validate_map, MapIssue, Severity},
+    },
+};
+
+fn main() {
+    let mut map_paths: Vec<String> = env::args().skip(1).collect();
+    if map_paths.is_empty() {
+        let folder = format!("{}/maps", ASSETS_FOLDER);
+        map_paths = list_map_files(&folder).unwrap_or_else(|error| {
+            eprintln!("could not read {}: {}", folder, error);
+            process::exit(1);
+        });
+    }
+
+    let manifest = LevelManifest::from_file(LEVEL_MANIFEST_PATH);
+    let mut errors = 0;
+    for map_path in map_paths.iter() {
+        let issues = check_map(map_path, &manifest);
+        for issue in issues.iter() {
+            println!("{}: {}", map_path, issue);
+        }
+        errors += issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+            .count();
+        if issues.is_empty() {
+            println!("{}: ok", map_path);
+        }
+    }
+
+    println!("{} maps checked, {} errors", map_paths.len(), errors);
+    if errors > 0 {
+        process::exit(1);
+    }
+}
+
+/// It parses and validates a single map
+///
+/// Arguments:
+///
+/// * `map_path`: The path of the map file
+/// * `manifest`: The levels, the map is checked with the bomb rule of the level that plays it
+///
+/// Returns:
+///
+/// Every issue found, parse and palette problems are reported as errors
+fn check_map(map_path: &str, manifest: &LevelManifest) -> Vec<MapIssue> {
+    let map = match fs::read(map_path)
+        .map_err(|error| error.to_string())
+        .and_then(|bytes| {
+            MapAsset::from_file_bytes(map_path, &bytes).map_err(|error| error.to_string())
+        }) {
+        Ok(map) => map,
+        Err(error) => return vec![MapIssue::error(error)],
+    };
+    let palette = match load_level_palette(map_path) {
+        Ok(palette) => palette.with_overrides(&map.palette),
+        Err(error) => return vec![MapIssue::error(error.to_string())],
+    };
+    validate_map(
+        &map,
+        &palette,
+        map.meta.starting_bombs(),
+        manifest.bomb_rule(map_path, &map),
+    )
+}
@@ -0,0 +1,135 @@
+//! Checks that the breakables of the maps can be cleared with the starting bombs.
+//! The bombs, range and bomb rule come from the map header unless they are given as options, the bomb
+//! rule of maps without one in their header comes from the level that plays them.
+//!
+//! ```sh
+//! cargo run --bin mapsolve
+//! cargo run --bin mapsolve -- --bombs 5 --range 2 assets/maps/level3.txt
+//! cargo run --bin mapsolve -- --rule capacity --bombs 1
+//! ```
+//!
+//! Exits with a non-zero code if any map can not be finished.
+
+use std::{env, fs, process};
+
+use minigame2::{
+    constants::{ASSETS_FOLDER, LEVEL_MANIFEST_PATH},
+    level::LevelManifest,
+    map::{
+        asset::{list_map_files, MapAsset},
+        meta::BombRule,
+        palette::load_level_palette,
+    },
+    solver::{solve, SolverConfig, SolverResult},
+};
+
+const MAX_STATES: usize = 200_000;
+
+fn main() {
+    let mut bombs = None;
+    let mut range = None;
+    let mut bomb_rule = None;
+    let mut map_paths = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bombs" => bombs = Some(parse_option(&arg, args.next())),
+            "--range" => range = Some(parse_option(&arg, args.next())),
+            "--rule" => match args.next().as_deref().and_then(BombRule::parse) {
+                Some(rule) => bomb_rule = Some(rule),
+                None => {
+                    eprintln!("--rule expects consumable or capacity");
+                    process::exit(2);
+                }
+            },
+            _ => map_paths.push(arg),
+        }
+    }
+    if map_paths.is_empty() {
+        let folder = format!("{}/maps", ASSETS_FOLDER);
+        map_paths = list_map_files(&folder).unwrap_or_else(|error| {
+            eprintln!("could not read {}: {}", folder, error);
+            process::exit(1);
+        });
+    }
+
+    let manifest = LevelManifest::from_file(LEVEL_MANIFEST_PATH);
+    let mut failed = 0;
+    for map_path in map_paths.iter() {
+        match solve_map(map_path, &manifest, bombs, range, bomb_rule) {
+            Ok((_, SolverResult::Solvable { bombs })) => {
+                println!("{}: solvable with {} bombs", map_path, bombs);
+            }
+            Ok((
+                config,
+                SolverResult::Unsolvable {
+                    bombs_needed: Some(bombs),
+                },
+            )) => {
+                println!(
+                    "{}: unsolvable, needs {} bombs but only {} are available",
+                    map_path, bombs, config.bombs
+                );
+                failed += 1;
+            }
+            Ok((_, SolverResult::Unsolvable { bombs_needed: None })) => {
+                println!(
+                    "{}: unsolvable, some breakables can not be reached",
+                    map_path
+                );
+                failed += 1;
+            }
+            Ok((config, SolverResult::LimitReached)) => {
+                println!(
+                    "{}: unknown, more than {} states searched",
+                    map_path, config.max_states
+                );
+            }
+            Err(error) => {
+                println!("{}: error: {}", map_path, error);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// It parses the value of a command line option, exiting if it is missing or invalid
+fn parse_option<T: std::str::FromStr>(name: &str, value: Option<String>) -> T {
+    match value.and_then(|value| value.parse().ok()) {
+        Some(value) => value,
+        None => {
+            eprintln!("{} expects a number", name);
+            process::exit(2);
+        }
+    }
+}
+
+/// It parses a map with its palette and runs the solver on it, the options replace the starting
+/// bombs, range and bomb rule of the map header
+fn solve_map(
+    map_path: &str,
+    manifest: &LevelManifest,
+    bombs: Option<u32>,
+    range: Option<f32>,
+    bomb_rule: Option<BombRule>,
+) -> Result<(SolverConfig, SolverResult), String> {
+    let bytes = fs::read(map_path).map_err(|error| error.to_string())?;
+    let map = MapAsset::from_file_bytes(map_path, &bytes).map_err(|error| error.to_string())?;
+    let palette = load_level_palette(map_path)
+        .map_err(|error| error.to_string())?
+        .with_overrides(&map.palette);
+    let config = SolverConfig {
+        spawn: map.spawn_cell(),
+        bombs: bombs.unwrap_or_else(|| map.meta.starting_bombs()),
+        range: range.unwrap_or_else(|| map.meta.starting_range()),
+        bomb_rule: bomb_rule.unwrap_or_else(|| manifest.bomb_rule(map_path, &map)),
+        max_states: MAX_STATES,
+    };
+    let result = solve(&map, &palette, &config);
+    Ok((config, result))
+}
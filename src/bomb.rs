@@ -1,13 +1,17 @@
-use std::time::Duration;
+use std::{collections::VecDeque, time::Duration};
 
 use bevy::prelude::*;
 use bevy_kira_audio::DynamicAudioChannels;
-use bevy_rapier3d::prelude::{ActiveCollisionTypes, ActiveEvents, Collider, Sensor};
 
 use crate::{
     audio::play_sfx,
     constants::{BOMB_EXPLOSTION_TIME, BOMB_SPAWN_DELAY, SFX_AUDIO_CHANNEL},
-    map::Breakable,
+    logic::LevelRules,
+    map::{
+        grid::{CellContent, GridMap},
+        meta::BombRule,
+        Breakable,
+    },
     player::Player,
     GameState,
 };
@@ -17,26 +21,35 @@ pub struct BombPlugin;
 #[derive(Component)]
 pub struct Bomb {
     explode_timer: Timer,
+    /// Range of the player when the bomb was placed
+    range: f32,
+}
+
+/// Sent when a bomb explodes, with the cell of the bomb and the cells reached by the explosion
+pub struct Explosion {
+    pub origin: IVec2,
+    pub cells: Vec<IVec2>,
 }
 #[derive(Component)]
 pub struct BombAmountText;
 
 impl Plugin for BombPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::on_update(GameState::Gameplay)
-                .with_system(drop_bomb)
-                .with_system(explode_bomb)
-                .with_system(update_text),
-        )
-        .add_system_set(SystemSet::on_enter(GameState::Gameplay).with_system(text_setup));
+        app.add_event::<Explosion>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Gameplay)
+                    .with_system(drop_bomb)
+                    .with_system(explode_bomb)
+                    .with_system(update_text),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Gameplay).with_system(text_setup));
     }
 }
 fn teste() {
     println!("opa");
 }
-/// It spawns a bomb when the player presses the spacebar, and the bomb explodes after a certain amount
-/// of time
+/// It spawns a bomb on the center of the cell of the player when the player presses the spacebar, and
+/// the bomb explodes after a certain amount of time. A cell only takes one bomb
 ///
 /// Arguments:
 ///
@@ -46,6 +59,7 @@ fn teste() {
 /// * `time`: Res<Time> - This is the time resource that is used to keep track of the time in the game.
 /// * `asset_server`: Res<AssetServer> - This is the resource that allows us to load assets.
 /// * `audio`: ResMut<DynamicAudioChannels> - This is the resource that allows us to play audio.
+/// * `grid_map`: ResMut<GridMap> - The logical map where the bomb cell is registered and checked
 fn drop_bomb(
     mut commands: Commands,
     mut player_query: Query<(&mut Player, &mut Transform)>,
@@ -53,33 +67,26 @@ fn drop_bomb(
     time: Res<Time>,
     asset_server: Res<AssetServer>,
     mut audio: ResMut<DynamicAudioChannels>,
+    mut grid_map: ResMut<GridMap>,
 ) {
     let (mut player, player_transform) = player_query.single_mut();
     let player_pos = player_transform.clone().translation;
     player.bomb_delay.tick(time.delta());
 
-    let bomb_explosion_range = vec![
-        (
-            Vec3::ZERO,
-            Quat::from_rotation_z(0.0),
-            Collider::cuboid(player.bomb_range, 0.1, 0.1),
-        ),
-        (
-            Vec3::ZERO,
-            Quat::from_rotation_z(0.0),
-            Collider::cuboid(0.1, 0.1, player.bomb_range),
-        ),
-    ];
+    //Bombs are placed on the grid, and never on top of another bomb
+    let bomb_cell = GridMap::world_to_cell(player_pos);
+    let cell_has_bomb = grid_map.get(bomb_cell) == Some(CellContent::Bomb);
 
     if player.bomb_delay.finished()
         && keyboard.just_pressed(KeyCode::Space)
         && player.bomb_amount > 0
+        && !cell_has_bomb
     {
         commands
             .spawn(SceneBundle {
                 scene: asset_server.load("objects/bomb.glb#Scene0"),
                 transform: Transform {
-                    translation: Vec3::new(player_pos.x, player_pos.y + 0.1, player_pos.z),
+                    translation: GridMap::cell_to_world(bomb_cell) + Vec3::Y * (player_pos.y + 0.1),
                     scale: Vec3::new(0.5, 0.5, 0.5),
                     ..Default::default()
                 },
@@ -91,11 +98,11 @@ fn drop_bomb(
                     Duration::from_secs(BOMB_EXPLOSTION_TIME),
                     TimerMode::Once,
                 ),
-            })
-            .insert(Collider::compound(bomb_explosion_range))
-            .insert(Sensor);
+                range: player.bomb_range,
+            });
         player.bomb_delay = Timer::new(Duration::from_millis(BOMB_SPAWN_DELAY), TimerMode::Once);
         player.bomb_amount -= 1; //Decrease amount
+        grid_map.set(bomb_cell, CellContent::Bomb);
         play_sfx(
             audio.create_channel(SFX_AUDIO_CHANNEL),
             asset_server.to_owned(),
@@ -104,48 +111,115 @@ fn drop_bomb(
     }
 }
 
-/// If the bomb's timer is finished, despawn the bomb and play the explosion sound.
+/// If the bomb's timer is finished, despawn the bomb and send the cells reached by the explosion on
+/// the grid. Bombs reached by an explosion go off in the same frame, and the explosion sound is played
+/// once for the whole chain. With the capacity rule each bomb that explodes goes back to the player
 ///
 /// Arguments:
 ///
 /// * `commands`: Commands - This is the command buffer that we will use to insert new entities into the
 /// world.
-/// * `bomb_query`: Query<(Entity, &mut Bomb), Without<Breakable>>
+/// * `bomb_query`: Query<(Entity, &mut Bomb, &Transform), Without<Breakable>>
 /// * `time`: Res<Time> - This is the time resource, which is used to keep track of the time in the
 /// game.
 /// * `asset_server`: Res<AssetServer>
 /// * `audio`: ResMut<DynamicAudioChannels>
+/// * `grid_map`: ResMut<GridMap> - The logical map, the bomb cells become floor again
+/// * `explosions`: EventWriter<Explosion>
+/// * `player_query`: Query<&mut Player>
+/// * `rules`: Res<LevelRules>
 fn explode_bomb(
     mut commands: Commands,
-    mut bomb_query: Query<(Entity, &mut Bomb), Without<Breakable>>,
+    mut bomb_query: Query<(Entity, &mut Bomb, &Transform), Without<Breakable>>,
+    mut player_query: Query<&mut Player>,
+    rules: Res<LevelRules>,
     time: Res<Time>,
     asset_server: Res<AssetServer>,
     mut audio: ResMut<DynamicAudioChannels>,
+    mut grid_map: ResMut<GridMap>,
+    mut explosions: EventWriter<Explosion>,
 ) {
-    for (bomb_entity, mut bomb) in bomb_query.iter_mut() {
+    let mut bombs = Vec::new();
+    for (bomb_entity, mut bomb, bomb_transform) in bomb_query.iter_mut() {
         // timers gotta be ticked, to work
         bomb.explode_timer.tick(time.delta());
+        bombs.push((
+            bomb_entity,
+            GridMap::world_to_cell(bomb_transform.translation),
+            bomb.range,
+            bomb.explode_timer.finished(),
+        ));
+    }
+    //Chains are resolved in the order of the cells, never in the order of the query
+    bombs.sort_by_key(|(_, cell, _, _)| (cell.y, cell.x));
+
+    let fuses: Vec<(IVec2, f32, bool)> = bombs
+        .iter()
+        .map(|(_, cell, range, finished)| (*cell, *range, *finished))
+        .collect();
+    let chain = chain_explosions(&mut grid_map, &fuses);
+    for (index, cells) in chain.iter() {
+        let (bomb_entity, bomb_cell, _, _) = bombs[*index];
+        //Despawn bomb
+        commands.entity(bomb_entity).despawn_recursive();
+        explosions.send(Explosion {
+            origin: bomb_cell,
+            cells: cells.clone(),
+        });
+    }
+
+    //Refill the bombs that went off
+    if rules.bomb_rule == BombRule::Capacity {
+        for mut player in player_query.iter_mut() {
+            player.bomb_amount += chain.len() as u32;
+        }
+    }
+
+    //Play explosion sound, a single one for all the bombs that went off in this frame
+    if !chain.is_empty() {
+        play_sfx(
+            audio.create_channel(SFX_AUDIO_CHANNEL),
+            asset_server.to_owned(),
+            String::from("audios/sfx/bomb_explosion.ogg"),
+        );
+    }
+}
+
+/// It explodes the bombs whose fuse ran out and every bomb reached by an explosion, the bombs reached
+/// go off right after the one that reached them
+///
+/// Arguments:
+///
+/// * `grid_map`: The logical map, the cells of the bombs that explode become floor
+/// * `bombs`: The cell, range and whether the fuse ran out of every bomb, in the order they go off
+///
+/// Returns:
+///
+/// The index of every bomb that explodes with the cells its explosion reaches, in the order they explode
+pub fn chain_explosions(
+    grid_map: &mut GridMap,
+    bombs: &[(IVec2, f32, bool)],
+) -> Vec<(usize, Vec<IVec2>)> {
+    let mut exploded: Vec<bool> = bombs.iter().map(|(_, _, finished)| *finished).collect();
+    let mut queue: VecDeque<usize> = (0..bombs.len()).filter(|index| exploded[*index]).collect();
+    let mut chain = Vec::new();
+    while let Some(index) = queue.pop_front() {
+        let (bomb_cell, range, _) = bombs[index];
+        grid_map.set(bomb_cell, CellContent::Floor);
+        let cells = grid_map.blast_cells(bomb_cell, GridMap::range_to_cells(range));
 
-        //Miliseconds before explode, add collider to despawn breakables
-        if bomb.explode_timer.percent_left() <= 0.01 {
-            commands
-                .entity(bomb_entity)
-                .insert(ActiveCollisionTypes::KINEMATIC_STATIC)
-                .insert(ActiveEvents::COLLISION_EVENTS);
-        };
-        // if it finished, despawn the bomb
-        if bomb.explode_timer.finished() {
-            //Despawn bomb
-            commands.entity(bomb_entity).despawn_recursive();
-
-            //Play explosion sound
-            play_sfx(
-                audio.create_channel(SFX_AUDIO_CHANNEL),
-                asset_server.to_owned(),
-                String::from("audios/sfx/bomb_explosion.ogg"),
-            );
+        //Bombs reached by the explosion go off right away
+        for cell in cells.iter() {
+            for (other, (other_cell, _, _)) in bombs.iter().enumerate() {
+                if other_cell == cell && !exploded[other] {
+                    exploded[other] = true;
+                    queue.push_back(other);
+                }
+            }
         }
+        chain.push((index, cells));
     }
+    chain
 }
 
 fn text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
@@ -179,13 +253,99 @@ fn text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     ));
 }
 
+/// It shows the bombs left, and with the capacity rule the bombs ready out of the most the player
+/// can have on the map
+///
+/// Arguments:
+///
+/// * `bomb_text_query`: Query<&mut Text, With<BombAmountText>>
+/// * `player_query`: Query<&Player>
+/// * `bomb_query`: Query<&Bomb>
+/// * `rules`: Res<LevelRules>
 fn update_text(
     mut bomb_text_query: Query<&mut Text, With<BombAmountText>>,
     player_query: Query<&Player>,
+    bomb_query: Query<&Bomb>,
+    rules: Res<LevelRules>,
 ) {
     let player = player_query.single();
+    let value = match rules.bomb_rule {
+        BombRule::Consumable => player.bomb_amount.to_string(),
+        BombRule::Capacity => format!(
+            "{}/{}",
+            player.bomb_amount,
+            player.bomb_amount + bomb_query.iter().count() as u32
+        ),
+    };
     for mut text in &mut bomb_text_query {
         // Update the value of the second section
-        text.sections[1].value = player.bomb_amount.to_string();
+        text.sections[1].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single row of floor with a bomb on each of `bombs`
+    fn grid_with_bombs(width: usize, bombs: &[(IVec2, f32, bool)]) -> GridMap {
+        let mut grid_map = GridMap::new(width, 1);
+        for x in 0..width {
+            grid_map.set(IVec2::new(x as i32, 0), CellContent::Floor);
+        }
+        for (cell, _, _) in bombs {
+            grid_map.set(*cell, CellContent::Bomb);
+        }
+        grid_map
+    }
+
+    #[test]
+    fn reached_bombs_go_off_after_the_one_that_reached_them() {
+        let bombs = [
+            (IVec2::new(1, 0), 1.0, false),
+            (IVec2::new(3, 0), 1.0, true),
+            (IVec2::new(5, 0), 0.5, false),
+            (IVec2::new(8, 0), 1.0, false),
+        ];
+        let mut grid_map = grid_with_bombs(9, &bombs);
+        let chain = chain_explosions(&mut grid_map, &bombs);
+
+        let order: Vec<usize> = chain.iter().map(|(index, _)| *index).collect();
+        assert_eq!(order.len(), 3);
+        assert_eq!(order[0], 1);
+        assert!(order.contains(&0) && order.contains(&2));
+        //The last bomb is out of the range of the others
+        assert!(!order.contains(&3));
+        assert_eq!(grid_map.get(IVec2::new(8, 0)), Some(CellContent::Bomb));
+        for index in order {
+            assert_eq!(grid_map.get(bombs[index].0), Some(CellContent::Floor));
+        }
+    }
+
+    #[test]
+    fn each_bomb_explodes_once() {
+        let bombs = [
+            (IVec2::new(1, 0), 1.0, true),
+            (IVec2::new(2, 0), 1.0, true),
+            (IVec2::new(3, 0), 1.0, false),
+        ];
+        let mut grid_map = grid_with_bombs(5, &bombs);
+        let chain = chain_explosions(&mut grid_map, &bombs);
+        let order: Vec<usize> = chain.iter().map(|(index, _)| *index).collect();
+        assert_eq!(order, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn walls_stop_the_chain() {
+        let bombs = [
+            (IVec2::new(1, 0), 1.0, true),
+            (IVec2::new(3, 0), 1.0, false),
+        ];
+        let mut grid_map = grid_with_bombs(5, &bombs);
+        grid_map.set(IVec2::new(2, 0), CellContent::Wall);
+        let chain = chain_explosions(&mut grid_map, &bombs);
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].0, 0);
+        assert!(!chain[0].1.contains(&IVec2::new(3, 0)));
     }
 }
@@ -1,19 +1,22 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
 use bevy_kira_audio::{DynamicAudioChannel, DynamicAudioChannels};
 use bevy_rapier3d::prelude::CollisionEvent;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     audio::play_sfx,
-    bomb::Bomb,
-    constants::SFX_AUDIO_CHANNEL,
-    logic::Flag,
-    map::Breakable,
+    constants::{SFX_AUDIO_CHANNEL, UPGRADE_FIRE_RANGE},
+    flame::Flame,
+    logic::{Flag, LevelRules},
+    map::{
+        grid::{CellContent, GridMap},
+        Breakable, Health,
+    },
     player::Player,
-    utils::{animate_interactive_items, possibly_spawn_upgrade},
+    utils::{animate_interactive_items, spawn_hidden_item},
     GameState,
 };
-#[derive(Component, Clone, Debug, Serialize, Deserialize, PartialEq, Copy)]
+#[derive(Component, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Copy)]
 pub enum UpgradeType {
     Bomb,
     Fire,
@@ -24,16 +27,20 @@ pub enum UpgradeType {
 pub struct InteractiveItem {
     pub upgrade: UpgradeType,
 }
+/// When the breakable of each cell was destroyed, it is emptied when the map is despawned or reloaded
+#[derive(Resource, Default)]
+pub struct ClearedCells(pub HashMap<IVec2, f64>);
+
 pub struct ColliderPlugin;
 
 impl Plugin for ColliderPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
+        app.init_resource::<ClearedCells>().add_system_set(
             SystemSet::on_update(GameState::Gameplay)
                 .with_system(player_and_item_collision_listener)
                 .with_system(player_and_flag_collision_listener)
                 .with_system(animate_interactive_items)
-                .with_system(explosion_collision_listener),
+                .with_system(flame_damage_listener),
         );
     }
 }
@@ -48,57 +55,55 @@ impl Plugin for ColliderPlugin {
 ///
 /// * `collision_events`: EventReader<CollisionEvent>
 /// * `player_query`: Query<(Entity, &mut Player), With<Player>>,
-/// * `interactive_query`: Query<(Entity, &InteractiveItem), Without<Player>>,
+/// * `interactive_query`: Query<(Entity, &InteractiveItem, &Transform), Without<Player>>,
 /// * `commands`: Commands - This is a struct that allows you to add, remove, and modify entities.
 /// * `asset_server`: Res<AssetServer> - This is the asset server, which is used to load assets.
 /// * `audio`: ResMut<DynamicAudioChannels>
+/// * `grid_map`: ResMut<GridMap> - The logical map, the item cell becomes floor again
 pub fn player_and_item_collision_listener(
     mut collision_events: EventReader<CollisionEvent>,
     mut player_query: Query<(Entity, &mut Player), With<Player>>,
-    interactive_query: Query<(Entity, &InteractiveItem), Without<Player>>,
+    interactive_query: Query<(Entity, &InteractiveItem, &Transform), Without<Player>>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut audio: ResMut<DynamicAudioChannels>,
+    mut grid_map: ResMut<GridMap>,
 ) {
     //Iterate over collision events
     for collision_event in collision_events.iter() {
         match collision_event {
             CollisionEvent::Started(entity_1, entity_2, _flags) => {
-                //If found an event, check if envolves the player
-                let (player_entity, mut player) = player_query.single_mut();
-                let has_player_collide = player_entity == *entity_1 || player_entity == *entity_2;
-
-                //If event is not related to player, ignore it, another
+                //If event is not related to a player and an item, ignore it, another
                 //listener should handle it
-                if !has_player_collide {
-                    break;
-                }
-                let item_entity = if player_entity == *entity_1 {
-                    entity_2
-                } else {
-                    entity_1
+                let (player_entity, item_entity) = match collision_pair(
+                    *entity_1,
+                    *entity_2,
+                    |entity| player_query.contains(entity),
+                    |entity| interactive_query.contains(entity),
+                ) {
+                    Some(pair) => pair,
+                    None => continue,
                 };
-
-                let is_interactive_item = interactive_query.contains(*item_entity);
-
-                if !is_interactive_item {
-                    break;
-                }
+                let (_player_entity, mut player) = player_query.get_mut(player_entity).unwrap();
 
                 // Despawn item and play sound
                 item_collision(
                     &mut commands,
-                    item_entity.to_owned(),
+                    item_entity,
                     asset_server.to_owned(),
                     audio.create_channel(SFX_AUDIO_CHANNEL),
                     String::from("audios/sfx/get_item.ogg"),
                 );
                 //Give Player Upgrade
-                let (_entidade, item) = interactive_query.get(*item_entity).unwrap();
+                let (_entidade, item, item_transform) = interactive_query.get(item_entity).unwrap();
+                grid_map.set(
+                    GridMap::world_to_cell(item_transform.translation),
+                    CellContent::Floor,
+                );
                 if item.upgrade == UpgradeType::Bomb {
                     player.bomb_amount += 1; //Give the player more bombs
                 } else if item.upgrade == UpgradeType::Fire {
-                    player.bomb_range += 1.0; //Bombs affects 1.0 more on explosion
+                    player.bomb_range += UPGRADE_FIRE_RANGE; //Bombs affects 1.0 more on explosion
                 } else {
                     player.speed += 0.2; //Player moves 0.2 times faster
                 }
@@ -110,86 +115,97 @@ pub fn player_and_item_collision_listener(
     }
 }
 
-/// It listens for collision events between bombs and breakables, and if it finds one, it destroys the
-/// breakable and spawns an item
+/// Every flame damages the breakables on its cell once. Damaged breakables change their scene,
+/// destroyed ones spawn the item the map hides under them, or a random one. Only the explosions that
+/// go off after a cell is cleared damage what appears on it, so a blast never destroys the item it
+/// reveals. The explosion sound is played by the bombs, not here
 ///
 /// Arguments:
 ///
-/// * `collision_events`: EventReader<CollisionEvent>
-/// * `bomb_query`: Query<Entity, With<Bomb>>,
-/// * `breakable_query`: Query<(Entity, &Breakable, &Transform), Without<Bomb>>,
+/// * `cleared_cells`: ResMut<ClearedCells> - When the breakable of each cell was destroyed
+/// * `flame_query`: Query<&mut Flame> - The flames of the explosions
+/// * `breakable_query`: Query<(Entity, &mut Health, &mut Handle<Scene>, &Transform), With<Breakable>>,
 /// * `commands`: Commands - This is the command buffer that we will use to spawn new entities.
 /// * `asset_server`: Res<AssetServer> - This is the asset server, which is used to load assets.
-/// * `audio`: ResMut<DynamicAudioChannels>
-pub fn explosion_collision_listener(
-    mut collision_events: EventReader<CollisionEvent>,
-    bomb_query: Query<Entity, With<Bomb>>,
-    breakable_query: Query<(Entity, &Breakable, &Transform), Without<Bomb>>,
+/// * `grid_map`: ResMut<GridMap> - The logical map, the breakable cell becomes floor again
+/// * `rules`: ResMut<LevelRules> - What the breakables of the level hide, each hidden item is revealed
+/// once
+/// * `time`: Res<Time>
+pub fn flame_damage_listener(
+    mut cleared_cells: ResMut<ClearedCells>,
+    mut flame_query: Query<&mut Flame>,
+    mut breakable_query: Query<
+        (Entity, &mut Health, &mut Handle<Scene>, &Transform),
+        With<Breakable>,
+    >,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut audio: ResMut<DynamicAudioChannels>,
+    mut grid_map: ResMut<GridMap>,
+    mut rules: ResMut<LevelRules>,
+    time: Res<Time>,
 ) {
-    //Iterate over collision events
-    for collision_event in collision_events.iter() {
-        match collision_event {
-            CollisionEvent::Started(entity_1, entity_2, _flags) => {
-                //If found an event, check if envolves the bomb explosion
-                let has_bomb_collide =
-                    bomb_query.contains(*entity_1) || bomb_query.contains(*entity_2);
-
-                //If found an event, check if envolves the breakable
-                let has_breakable_collide =
-                    breakable_query.contains(*entity_1) || breakable_query.contains(*entity_2);
-
-                //If event is not related to bomb and breakables, ignore it, another
-                //listener should handle it
-                if !has_bomb_collide || !has_breakable_collide {
-                    break;
-                }
-
-                //Get the breakable entity
-                let breakable_entity = if breakable_query.contains(*entity_1) {
-                    entity_1
-                } else {
-                    entity_2
-                };
-                let (_, _breakable, breakable_transform) =
-                    breakable_query.get(*breakable_entity).unwrap();
-
-                // Despawn breakable and play explosion sound
-                item_collision(
-                    &mut commands,
-                    breakable_entity.to_owned(),
-                    asset_server.to_owned(),
-                    audio.create_channel(SFX_AUDIO_CHANNEL),
-                    String::from("audios/sfx/bomb_explosion.ogg"),
-                );
-                //May or May not spawn an upgrade on despawn breakable
-                possibly_spawn_upgrade(
-                    &mut commands,
-                    &asset_server,
-                    breakable_transform.translation,
-                );
+    //Iterate over the flames
+    for mut flame in flame_query.iter_mut() {
+        //The cell was cleared after the explosion went off, what is there now came later
+        if let Some(cleared_at) = cleared_cells.0.get(&flame.cell) {
+            if *cleared_at >= flame.lit_at {
+                continue;
             }
-            CollisionEvent::Stopped(_e1, _e2, _flags) => {
-                // Collision OUT
+        }
+        for (breakable_entity, mut health, mut scene, breakable_transform) in
+            breakable_query.iter_mut()
+        {
+            let breakable_cell = GridMap::world_to_cell(breakable_transform.translation);
+            //Already destroyed by another flame of this frame, or already damaged by this one
+            if breakable_cell != flame.cell
+                || health.hits == 0
+                || flame.damaged.contains(&breakable_entity)
+            {
+                continue;
             }
+            flame.damaged.push(breakable_entity);
+            if !health.hit() {
+                //Show the damage and keep the breakable
+                if let Some(path) = health.damaged_path() {
+                    *scene = asset_server.load(path);
+                }
+                continue;
+            }
+
+            // Despawn breakable
+            commands.entity(breakable_entity).despawn_recursive();
+            grid_map.set(breakable_cell, CellContent::Floor);
+            cleared_cells
+                .0
+                .insert(breakable_cell, time.elapsed_seconds_f64());
+            //Spawn the hidden item, or May or May not spawn an upgrade on despawn breakable
+            spawn_hidden_item(
+                &mut commands,
+                &asset_server,
+                &mut grid_map,
+                rules.hidden.remove(&breakable_cell),
+                breakable_transform.translation,
+            );
         }
     }
 }
 
-/// If the player and the flag collide, despawn the flag
+/// If the player and a flag collide, despawn the flag and finish the level. Levels can have a flag
+/// on each exit point, any of them finishes the level
 ///
 /// Arguments:
 ///
 /// * `collision_events`: EventReader<CollisionEvent>
-/// * `player_query`: Query<(Entity, &mut Player), With<Player>>
-/// * `flag_query`: Query<(Entity, &mut Flag), Without<Player>>,
+/// * `player_query`: Query<Entity, With<Player>>
+/// * `flag_query`: Query<Entity, (With<Flag>, Without<Player>)>,
 /// * `commands`: Commands
+/// * `game_state`: ResMut<State<GameState>>
+/// * `asset_server`: Res<AssetServer>
+/// * `audio`: ResMut<DynamicAudioChannels>
 pub fn player_and_flag_collision_listener(
     mut collision_events: EventReader<CollisionEvent>,
-    mut player_query: Query<(Entity, &mut Player), With<Player>>,
-    mut flag_query: Query<(Entity, &mut Flag), Without<Player>>,
+    player_query: Query<Entity, With<Player>>,
+    flag_query: Query<Entity, (With<Flag>, Without<Player>)>,
     mut commands: Commands,
     mut game_state: ResMut<State<GameState>>,
     asset_server: Res<AssetServer>,
@@ -199,33 +215,27 @@ pub fn player_and_flag_collision_listener(
     for collision_event in collision_events.iter() {
         match collision_event {
             CollisionEvent::Started(entity_1, entity_2, _flags) => {
-                if flag_query.is_empty() {
-                    break;
-                }
-
-                //If found an event, check if envolves the player
-                let (player_entity, _player) = player_query.single_mut();
-                let (flag_entity, _flag) = flag_query.single_mut();
-
-                let has_player_collide = player_entity == *entity_1 || player_entity == *entity_2;
-                let has_flag_collide = flag_entity == *entity_1 || flag_entity == *entity_2;
-                println!(
-                    "Player and flag collided ? {:?}",
-                    has_player_collide && has_flag_collide
-                );
-                //If event is not related to player or flag, ignore it, another
+                //If event is not related to a player and a flag, ignore it, another
                 //listener should handle it
-                if !has_player_collide || !has_flag_collide {
-                    break;
-                }
+                let flag_entity = match collision_pair(
+                    *entity_1,
+                    *entity_2,
+                    |entity| player_query.contains(entity),
+                    |entity| flag_query.contains(entity),
+                ) {
+                    Some((_player_entity, flag_entity)) => flag_entity,
+                    None => continue,
+                };
                 item_collision(
                     &mut commands,
-                    flag_entity.to_owned(),
+                    flag_entity,
                     asset_server.to_owned(),
                     audio.create_channel(SFX_AUDIO_CHANNEL),
                     String::from("audios/sfx/won_level_1.ogg"),
                 );
-                game_state.set(GameState::NextLevel);
+                //The state may already be changing if the player died this frame
+                let _ = game_state.set(GameState::NextLevel);
+                return;
             }
             CollisionEvent::Stopped(_e1, _e2, _flags) => {
                 // Collision OUT
@@ -234,6 +244,33 @@ pub fn player_and_flag_collision_listener(
     }
 }
 
+/// It finds which entity of a collision is on each side, in any order
+///
+/// Arguments:
+///
+/// * `entity_1`: The first entity of the collision
+/// * `entity_2`: The second entity of the collision
+/// * `is_first`: Whether an entity is on the first side, like a player
+/// * `is_second`: Whether an entity is on the second side, like a flag
+///
+/// Returns:
+///
+/// The entity of each side, or None if the collision is not between both sides
+pub fn collision_pair(
+    entity_1: Entity,
+    entity_2: Entity,
+    is_first: impl Fn(Entity) -> bool,
+    is_second: impl Fn(Entity) -> bool,
+) -> Option<(Entity, Entity)> {
+    if is_first(entity_1) && is_second(entity_2) {
+        Some((entity_1, entity_2))
+    } else if is_first(entity_2) && is_second(entity_1) {
+        Some((entity_2, entity_1))
+    } else {
+        None
+    }
+}
+
 /// Despawn the item entity and play a sound effect.
 ///
 /// Arguments:
@@ -255,3 +292,28 @@ pub fn item_collision(
     //Play Sound Effect
     play_sfx(audio, asset_server, audio_source)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_flag_of_a_two_exit_map_is_touched() {
+        let player = Entity::from_raw(0);
+        let flags = [Entity::from_raw(1), Entity::from_raw(2)];
+        let wall = Entity::from_raw(3);
+        let is_player = |entity: Entity| entity == player;
+        let is_flag = |entity: Entity| flags.contains(&entity);
+
+        assert_eq!(
+            collision_pair(player, flags[0], is_player, is_flag),
+            Some((player, flags[0]))
+        );
+        assert_eq!(
+            collision_pair(flags[1], player, is_player, is_flag),
+            Some((player, flags[1]))
+        );
+        assert_eq!(collision_pair(player, wall, is_player, is_flag), None);
+        assert_eq!(collision_pair(flags[0], flags[1], is_player, is_flag), None);
+    }
+}
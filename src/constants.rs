@@ -8,9 +8,39 @@ pub const WIDTH: f32 = 1280.0;
 //Bomb
 pub const BOMB_SPAWN_DELAY: u64 = 350;
 pub const BOMB_EXPLOSTION_TIME: u64 = 3;
+//Seconds the flames of an explosion stay on the map
+pub const FLAME_LIFETIME: f32 = 0.5;
+
+//Player
+pub const PLAYER_SPAWN_POSITION: Vec3 = Vec3::new(0.1, 0.4, -0.1);
+pub const PLAYER_BOMB_AMOUNT: u32 = 10;
+pub const PLAYER_BOMB_RANGE: f32 = 1.0;
+pub const PLAYER_SPEED: f32 = 1.0;
+pub const PLAYER_LIFE: f32 = 2.0;
+//Life lost by the player on each flame that reaches it
+pub const EXPLOSION_DAMAGE: f32 = 1.0;
+//Seconds the player can't be damaged after a hit, and how fast it blinks meanwhile
+pub const PLAYER_INVULNERABILITY_TIME: f32 = 1.5;
+pub const PLAYER_BLINK_INTERVAL: f32 = 0.1;
+
+//Logic
+pub const FLAG_POSITION: Vec3 = Vec3::new(0.1, 0.2, -0.1);
+
+//Upgrades
+pub const UPGRADE_FIRE_RANGE: f32 = 1.0;
 
 //Audio
 pub const SFX_AUDIO_CHANNEL: &str = "sfx";
 
 //Map
 pub const DEFAULT_OBJECT_SCALE: &Vec3 = &Vec3::new(0.57, 1., 0.57);
+pub const MAP_CELL_SIZE: f32 = 0.5;
+pub const MAP_ORIGIN: Vec3 = Vec3::new(-6., 0., -4.);
+pub const MAP_PALETTE_PATH: &str = "assets/maps/palette.ron";
+pub const DEFAULT_FLOOR_PATH: &str = "objects/tile.glb#Scene0";
+pub const THEMES_PATH: &str = "assets/themes.ron";
+pub const MAP_EXPORT_FOLDER: &str = "assets/maps/exports";
+
+//Level
+pub const ASSETS_FOLDER: &str = "assets";
+pub const LEVEL_MANIFEST_PATH: &str = "assets/levels.ron";
@@ -0,0 +1,538 @@
+use std::fs;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    collider::UpgradeType,
+    constants::{ASSETS_FOLDER, FLAG_POSITION, MAP_ORIGIN, PLAYER_SPAWN_POSITION},
+    level::{CurrentLevel, LevelManifest},
+    map::{
+        asset::{text_map_path, MapAsset, NO_FLOOR_CHAR},
+        generate::generate,
+        grid::GridMap,
+        hidden::HiddenItem,
+        load_palette,
+        palette::MapPalette,
+        spawn_map,
+        theme::ThemeSet,
+        CurrentMap,
+    },
+    utils::MapObject,
+    GameState,
+};
+
+pub struct EditorPlugin;
+
+/// What a click on the grid paints
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Brush {
+    Tile(char),
+    Spawn,
+    Exit,
+    /// What the breakable of the cell leaves behind
+    Hidden(HiddenItem),
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Brush::Tile(' ')
+    }
+}
+
+/// The map being edited, it is kept while the level is test played
+#[derive(Resource, Default)]
+pub struct EditorState {
+    pub map: Option<MapAsset>,
+    /// Where the map is saved, relative to the assets folder
+    pub path: String,
+    pub brush: Brush,
+    /// Tiles are painted on the floor layer instead of the object layer
+    pub floor_layer: bool,
+    /// The map is being played from the editor, F2 goes back to it
+    pub testing: bool,
+    /// The map changed and the preview has to be spawned again
+    pub changed: bool,
+    pub message: String,
+}
+
+/// Spawn and exit points shown while editing
+#[derive(Component)]
+pub struct EditorMarker;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorState>()
+            .add_system_set(SystemSet::on_enter(GameState::Editor).with_system(setup_editor))
+            .add_system_set(
+                SystemSet::on_update(GameState::Editor)
+                    .with_system(editor_panel)
+                    .with_system(paint_cells)
+                    .with_system(refresh_preview),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(despawn_preview))
+            .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(back_to_editor))
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(stop_testing))
+            .add_system_set(SystemSet::on_enter(GameState::NextLevel).with_system(stop_testing));
+    }
+}
+
+/// It opens the map of the current level in the editor, or keeps the edited one when coming back
+/// from a test play
+///
+/// Arguments:
+///
+/// * `manifest`: Res<LevelManifest>
+/// * `current_level`: Res<CurrentLevel>
+/// * `themes`: Res<ThemeSet>
+/// * `editor`: ResMut<EditorState>
+/// * `current_map`: ResMut<CurrentMap> - The level settings are kept here for the test play
+/// * `palette`: ResMut<MapPalette> - The palette the tiles are painted with
+/// * `game_state`: ResMut<State<GameState>>
+fn setup_editor(
+    manifest: Res<LevelManifest>,
+    current_level: Res<CurrentLevel>,
+    themes: Res<ThemeSet>,
+    mut editor: ResMut<EditorState>,
+    mut current_map: ResMut<CurrentMap>,
+    mut palette: ResMut<MapPalette>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    let level = manifest.get(&current_level);
+    current_map.set_level(level);
+
+    if !editor.testing || editor.map.is_none() {
+        let map = match &level.generator {
+            Some(generator) => generate(generator, MapAsset::default().spawn_cell()),
+            None => read_map(&current_map.path),
+        };
+        match map {
+            Ok(map) => editor.map = Some(map),
+            Err(error) => {
+                error!(
+                    "Could not open {} in the editor: {}",
+                    current_map.path, error
+                );
+                game_state.set(GameState::Menu).unwrap();
+                return;
+            }
+        }
+        editor.path = current_map.path.clone();
+        editor.message = String::new();
+    }
+    editor.testing = false;
+    editor.changed = true;
+
+    let map = editor.map.as_ref().unwrap();
+    *palette = match load_palette(
+        &format!("{}/{}", ASSETS_FOLDER, editor.path),
+        map,
+        &current_map.theme(map, &themes),
+    ) {
+        Ok(palette) => palette,
+        Err(error) => {
+            error!("Could not open {} in the editor: {}", editor.path, error);
+            game_state.set(GameState::Menu).unwrap();
+            return;
+        }
+    };
+}
+
+/// It reads a map file of the assets folder
+fn read_map(path: &str) -> Result<MapAsset, String> {
+    let bytes =
+        fs::read(format!("{}/{}", ASSETS_FOLDER, path)).map_err(|error| error.to_string())?;
+    MapAsset::from_file_bytes(path, &bytes).map_err(|error| error.to_string())
+}
+
+/// Height of the hidden item markers, over the breakables
+const HIDDEN_MARKER_HEIGHT: f32 = 0.6;
+
+/// What can be hidden under a breakable, in the order of the panel
+const HIDDEN_BRUSHES: [HiddenItem; 5] = [
+    HiddenItem::Upgrade(UpgradeType::Bomb),
+    HiddenItem::Upgrade(UpgradeType::Fire),
+    HiddenItem::Upgrade(UpgradeType::Speed),
+    HiddenItem::Nothing,
+    HiddenItem::Exit,
+];
+
+/// It shows the editor panel: the brushes, the map path and the open, save, test play and exit buttons
+///
+/// Arguments:
+///
+/// * `egui_context`: ResMut<EguiContext>
+/// * `editor`: ResMut<EditorState>
+/// * `palette`: Res<MapPalette>
+/// * `maps`: ResMut<Assets<MapAsset>> - Where the map is added to be test played
+/// * `current_map`: ResMut<CurrentMap>
+/// * `game_state`: ResMut<State<GameState>>
+fn editor_panel(
+    mut egui_context: ResMut<EguiContext>,
+    mut editor: ResMut<EditorState>,
+    palette: Res<MapPalette>,
+    mut maps: ResMut<Assets<MapAsset>>,
+    mut current_map: ResMut<CurrentMap>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    let mut tiles: Vec<(char, String)> = palette
+        .tiles
+        .iter()
+        .map(|(key, props)| (*key, props.name.clone()))
+        .collect();
+    tiles.sort();
+
+    let editor = &mut *editor;
+    egui::SidePanel::left("editor_panel").show(egui_context.ctx_mut(), |ui| {
+        ui.heading("Editor");
+        ui.label("Left click paints, right click erases");
+        ui.separator();
+
+        for (key, name) in tiles.iter() {
+            ui.selectable_value(
+                &mut editor.brush,
+                Brush::Tile(*key),
+                format!("{:?} {}", key, name),
+            );
+        }
+        ui.selectable_value(&mut editor.brush, Brush::Spawn, "Spawn point");
+        ui.selectable_value(&mut editor.brush, Brush::Exit, "Exit point");
+        ui.label("Hidden under breakables");
+        for item in HIDDEN_BRUSHES {
+            ui.selectable_value(&mut editor.brush, Brush::Hidden(item), item.to_string());
+        }
+        ui.checkbox(&mut editor.floor_layer, "Paint the floor layer");
+        ui.separator();
+
+        ui.label("Map file");
+        ui.text_edit_singleline(&mut editor.path);
+        //Exported levels are opened from maps/exports
+        if ui.button("Open").clicked() {
+            editor.message = match read_map(&editor.path) {
+                Ok(map) => {
+                    editor.map = Some(map);
+                    editor.changed = true;
+                    format!("Opened {}", editor.path)
+                }
+                Err(error) => format!("Could not open: {}", error),
+            };
+        }
+        if ui.button("Save").clicked() {
+            editor.message = match save_map(&mut editor) {
+                Ok(()) => format!("Saved {}", editor.path),
+                Err(error) => format!("Could not save: {}", error),
+            };
+        }
+        if ui.button("Test play").clicked() {
+            if let Some(map) = &editor.map {
+                current_map.path = editor.path.clone();
+                current_map.handle = maps.add(map.clone());
+                editor.testing = true;
+                game_state.set(GameState::Gameplay).unwrap();
+            }
+        }
+        if ui.button("Exit").clicked() {
+            game_state.set(GameState::Menu).unwrap();
+        }
+        ui.label(editor.message.as_str());
+    });
+}
+
+/// It writes the edited map to its file, Tiled maps are saved as a text map next to them and the
+/// editor keeps working on the text map
+fn save_map(editor: &mut EditorState) -> Result<(), String> {
+    let map = editor.map.as_ref().ok_or("no map is being edited")?;
+    let path = text_map_path(&editor.path);
+    map.save(&format!("{}/{}", ASSETS_FOLDER, path))?;
+    editor.path = path;
+    Ok(())
+}
+
+/// It paints the brush on the cell under the mouse, the right button turns the cell back into floor
+///
+/// Arguments:
+///
+/// * `egui_context`: ResMut<EguiContext> - Clicks on the panel are not painted
+/// * `mouse`: Res<Input<MouseButton>>
+/// * `windows`: Res<Windows>
+/// * `camera_query`: Query<(&Camera, &GlobalTransform)>
+/// * `editor`: ResMut<EditorState>
+fn paint_cells(
+    mut egui_context: ResMut<EguiContext>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut editor: ResMut<EditorState>,
+) {
+    let erase = mouse.pressed(MouseButton::Right);
+    if !mouse.pressed(MouseButton::Left) && !erase {
+        return;
+    }
+    if egui_context.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+    let cell = match cursor_cell(&windows, &camera_query) {
+        Some(cell) => cell,
+        None => return,
+    };
+    let brush = if erase {
+        Brush::Tile(' ')
+    } else {
+        editor.brush
+    };
+    let floor_layer = editor.floor_layer;
+    let map = match editor.map.as_mut() {
+        Some(map) => map,
+        None => return,
+    };
+    let changed = match brush {
+        Brush::Tile(char) if floor_layer => paint_floor(map, cell, erase_floor(char, erase)),
+        _ => paint(map, cell, brush),
+    };
+    if changed {
+        editor.changed = true;
+    }
+}
+
+/// It returns the cell of the ground under the mouse
+fn cursor_cell(
+    windows: &Windows,
+    camera_query: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<IVec2> {
+    let cursor = windows.get_primary()?.cursor_position()?;
+    let (camera, camera_transform) = camera_query.iter().next()?;
+    let ray = camera.viewport_to_world(camera_transform, cursor)?;
+    if ray.direction.y.abs() < f32::EPSILON {
+        return None;
+    }
+    let distance = (MAP_ORIGIN.y - ray.origin.y) / ray.direction.y;
+    if distance < 0.0 {
+        return None;
+    }
+    Some(GridMap::world_to_cell(
+        ray.origin + ray.direction * distance,
+    ))
+}
+
+/// It paints a brush on a cell of the map
+///
+/// Arguments:
+///
+/// * `map`: The map being edited
+/// * `cell`: The painted cell
+/// * `brush`: What is painted
+///
+/// Returns:
+///
+/// If the map changed
+fn paint(map: &mut MapAsset, cell: IVec2, brush: Brush) -> bool {
+    if cell.x < 0 || cell.y < 0 {
+        return false;
+    }
+    let tile = match map
+        .rows
+        .get_mut(cell.y as usize)
+        .and_then(|row| row.get_mut(cell.x as usize))
+    {
+        Some(tile) => tile,
+        None => return false,
+    };
+    let was_spawn = map.spawn_points.contains(&cell);
+    let was_exit = map.exit_points.contains(&cell);
+
+    match brush {
+        Brush::Tile(char) => {
+            if *tile == char && !was_spawn && !was_exit {
+                return false;
+            }
+            *tile = char;
+            map.spawn_points.retain(|point| *point != cell);
+            map.exit_points.retain(|point| *point != cell);
+            //A new tile does not hide what the old one did
+            map.hidden.remove(&cell);
+        }
+        Brush::Hidden(item) => {
+            if map.hidden.get(&cell) == Some(&item) {
+                return false;
+            }
+            map.hidden.insert(cell, item);
+        }
+        Brush::Spawn | Brush::Exit => {
+            if (brush == Brush::Spawn && was_spawn) || (brush == Brush::Exit && was_exit) {
+                return false;
+            }
+            //Points stand on the floor
+            *tile = ' ';
+            map.spawn_points.retain(|point| *point != cell);
+            map.exit_points.retain(|point| *point != cell);
+            map.hidden.remove(&cell);
+            if brush == Brush::Spawn {
+                map.spawn_points.push(cell);
+            } else {
+                map.exit_points.push(cell);
+            }
+        }
+    }
+    true
+}
+
+/// The right button clears the floor layer, so the cell takes the floor of the object layer again
+fn erase_floor(char: char, erase: bool) -> char {
+    if erase {
+        NO_FLOOR_CHAR
+    } else {
+        char
+    }
+}
+
+/// It paints a tile on the floor layer, the layer grows to the size of the map when it is first painted
+///
+/// Arguments:
+///
+/// * `map`: The map being edited
+/// * `cell`: The painted cell
+/// * `char`: The floor tile
+///
+/// Returns:
+///
+/// If the map changed
+fn paint_floor(map: &mut MapAsset, cell: IVec2, char: char) -> bool {
+    if map.get(cell).is_none() {
+        return false;
+    }
+    let width = map.width();
+    map.floor_rows.resize(map.height(), Vec::new());
+    for row in map.floor_rows.iter_mut() {
+        if row.len() < width {
+            row.resize(width, NO_FLOOR_CHAR);
+        }
+    }
+    let tile = &mut map.floor_rows[cell.y as usize][cell.x as usize];
+    if *tile == char {
+        return false;
+    }
+    *tile = char;
+    true
+}
+
+/// It spawns the edited map again when it changes
+///
+/// Arguments:
+///
+/// * `commands`: Commands
+/// * `asset_server`: Res<AssetServer>
+/// * `editor`: ResMut<EditorState>
+/// * `palette`: Res<MapPalette>
+/// * `map_objects`: Query<Entity, With<MapObject>>
+fn refresh_preview(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut editor: ResMut<EditorState>,
+    palette: Res<MapPalette>,
+    map_objects: Query<Entity, With<MapObject>>,
+) {
+    if !editor.changed {
+        return;
+    }
+    editor.changed = false;
+    let map = match &editor.map {
+        Some(map) => map,
+        None => return,
+    };
+
+    for object in map_objects.iter() {
+        commands.entity(object).despawn_recursive();
+    }
+    //Characters that are not in the palette are left empty instead of panicking
+    let mut preview = map.clone();
+    for row in preview.rows.iter_mut() {
+        for tile in row.iter_mut() {
+            if palette.get(*tile).is_none() {
+                *tile = ' ';
+            }
+        }
+    }
+    for row in preview.floor_rows.iter_mut() {
+        for tile in row.iter_mut() {
+            if !matches!(palette.get(*tile), Some(props) if props.is_floor) {
+                *tile = NO_FLOOR_CHAR;
+            }
+        }
+    }
+    spawn_map(&mut commands, &asset_server, &preview, &palette);
+
+    let markers = map
+        .spawn_points
+        .iter()
+        .map(|cell| {
+            (
+                "objects/enemy_ufoRedWeapon.glb#Scene0",
+                *cell,
+                PLAYER_SPAWN_POSITION.y,
+            )
+        })
+        .chain(
+            map.exit_points
+                .iter()
+                .map(|cell| ("objects/flag.glb#Scene0", *cell, FLAG_POSITION.y)),
+        )
+        //Hidden items float over their breakable
+        .chain(map.hidden.iter().filter_map(|(cell, item)| {
+            let path = match item {
+                HiddenItem::Upgrade(UpgradeType::Bomb) => "objects/bombup.glb#Scene0",
+                HiddenItem::Upgrade(UpgradeType::Fire) => "objects/fireup.glb#Scene0",
+                HiddenItem::Upgrade(UpgradeType::Speed) => "objects/speedup.glb#Scene0",
+                HiddenItem::Exit => "objects/flag.glb#Scene0",
+                HiddenItem::Upgrade(UpgradeType::None) | HiddenItem::Nothing => return None,
+            };
+            Some((path, *cell, HIDDEN_MARKER_HEIGHT))
+        }));
+    for (path, cell, height) in markers {
+        commands
+            .spawn(SceneBundle {
+                scene: asset_server.load(path),
+                transform: Transform {
+                    translation: GridMap::cell_to_world(cell) + Vec3::Y * height,
+                    scale: Vec3::new(0.5, 0.8, 0.5),
+                    ..default()
+                },
+                ..default()
+            })
+            .insert(EditorMarker)
+            .insert(MapObject)
+            .insert(Name::new("EditorMarker"));
+    }
+}
+
+fn despawn_preview(mut commands: Commands, map_objects: Query<Entity, With<MapObject>>) {
+    for object in map_objects.iter() {
+        commands.entity(object).despawn_recursive();
+    }
+}
+
+/// While a level is test played from the editor, F2 goes back to the editor
+///
+/// Arguments:
+///
+/// * `keyboard`: Res<Input<KeyCode>>
+/// * `editor`: Res<EditorState>
+/// * `game_state`: ResMut<State<GameState>>
+fn back_to_editor(
+    keyboard: Res<Input<KeyCode>>,
+    editor: Res<EditorState>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    if editor.testing && keyboard.just_pressed(KeyCode::F2) {
+        let _ = game_state.set(GameState::Editor);
+    }
+}
+
+/// When the test play ends without going back to the editor, the next games are normal ones and
+/// the editor opens the map of the level again
+///
+/// Arguments:
+///
+/// * `editor`: ResMut<EditorState>
+fn stop_testing(mut editor: ResMut<EditorState>) {
+    editor.testing = false;
+}
@@ -0,0 +1,204 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::{
+    bomb::Explosion,
+    constants::{FLAME_LIFETIME, MAP_CELL_SIZE},
+    map::grid::GridMap,
+    GameState,
+};
+
+pub struct FlamePlugin;
+
+/// Part of the cross drawn by an explosion
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlamePiece {
+    /// The cell of the bomb
+    Centre,
+    /// A cell the explosion goes through
+    Arm,
+    /// The last cell reached in a direction
+    Tip,
+}
+
+/// Fire left on a cell reached by an explosion, it damages what enters its cell until its lifetime ends
+#[derive(Component)]
+pub struct Flame {
+    pub cell: IVec2,
+    pub piece: FlamePiece,
+    pub lifetime: Timer,
+    /// Entities already damaged by the flame, each one is damaged once
+    pub damaged: Vec<Entity>,
+    /// Seconds since startup when the explosion went off, what appears on the cell after it is not
+    /// damaged
+    pub lit_at: f64,
+}
+
+/// Meshes and material shared by every flame
+#[derive(Resource)]
+pub struct FlameAssets {
+    centre: Handle<Mesh>,
+    arm: Handle<Mesh>,
+    tip: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for FlameAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        //Arms and tips are modelled along the x axis
+        let centre = meshes.add(Mesh::from(shape::Box::new(
+            MAP_CELL_SIZE * 0.8,
+            0.3,
+            MAP_CELL_SIZE * 0.8,
+        )));
+        let arm = meshes.add(Mesh::from(shape::Box::new(
+            MAP_CELL_SIZE,
+            0.2,
+            MAP_CELL_SIZE * 0.5,
+        )));
+        let tip = meshes.add(Mesh::from(shape::Box::new(
+            MAP_CELL_SIZE * 0.7,
+            0.15,
+            MAP_CELL_SIZE * 0.4,
+        )));
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let material = materials.add(StandardMaterial {
+            base_color: Color::rgba(1.0, 0.45, 0.0, 0.8),
+            emissive: Color::rgb(1.0, 0.3, 0.0),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        });
+        FlameAssets {
+            centre,
+            arm,
+            tip,
+            material,
+        }
+    }
+}
+
+impl Plugin for FlamePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FlameAssets>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Gameplay)
+                    .with_system(spawn_flames)
+                    .with_system(burn_out_flames),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Gameplay).with_system(despawn_flames));
+    }
+}
+
+/// It returns the piece of the cross each cell of an explosion is drawn with, and the direction the
+/// explosion goes through it
+///
+/// Arguments:
+///
+/// * `origin`: The cell of the bomb
+/// * `cells`: The cells reached by the explosion
+///
+/// Returns:
+///
+/// The piece and direction of every cell, the centre has no direction
+pub fn flame_pieces(origin: IVec2, cells: &[IVec2]) -> Vec<(IVec2, FlamePiece, IVec2)> {
+    cells
+        .iter()
+        .map(|cell| {
+            if *cell == origin {
+                return (*cell, FlamePiece::Centre, IVec2::ZERO);
+            }
+            let direction = (*cell - origin).signum();
+            let piece = if cells.contains(&(*cell + direction)) {
+                FlamePiece::Arm
+            } else {
+                FlamePiece::Tip
+            };
+            (*cell, piece, direction)
+        })
+        .collect()
+}
+
+/// It spawns the flames of every explosion, one on each cell it reaches
+///
+/// Arguments:
+///
+/// * `commands`: Commands
+/// * `explosions`: EventReader<Explosion>
+/// * `flame_assets`: Res<FlameAssets>
+/// * `time`: Res<Time>
+fn spawn_flames(
+    mut commands: Commands,
+    mut explosions: EventReader<Explosion>,
+    flame_assets: Res<FlameAssets>,
+    time: Res<Time>,
+) {
+    for explosion in explosions.iter() {
+        for (cell, piece, direction) in flame_pieces(explosion.origin, &explosion.cells) {
+            let mut translation = GridMap::cell_to_world(cell) + Vec3::Y * 0.3;
+            let mesh = match piece {
+                FlamePiece::Centre => flame_assets.centre.clone(),
+                FlamePiece::Arm => flame_assets.arm.clone(),
+                FlamePiece::Tip => {
+                    //Tips end before the edge of their cell
+                    translation -= Vec3::new(direction.x as f32, 0.0, direction.y as f32)
+                        * MAP_CELL_SIZE
+                        * 0.15;
+                    flame_assets.tip.clone()
+                }
+            };
+            let rotation = if direction.y != 0 {
+                Quat::from_rotation_y(PI / 2.0)
+            } else {
+                Quat::IDENTITY
+            };
+            commands
+                .spawn(PbrBundle {
+                    mesh,
+                    material: flame_assets.material.clone(),
+                    transform: Transform {
+                        translation,
+                        rotation,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(Name::new("Flame"))
+                .insert(Flame {
+                    cell,
+                    piece,
+                    lifetime: Timer::from_seconds(FLAME_LIFETIME, TimerMode::Once),
+                    damaged: Vec::new(),
+                    lit_at: time.elapsed_seconds_f64(),
+                });
+        }
+    }
+}
+
+/// It despawns the flames whose lifetime ended
+///
+/// Arguments:
+///
+/// * `commands`: Commands
+/// * `flame_query`: Query<(Entity, &mut Flame)>
+/// * `time`: Res<Time>
+fn burn_out_flames(
+    mut commands: Commands,
+    mut flame_query: Query<(Entity, &mut Flame)>,
+    time: Res<Time>,
+) {
+    for (flame_entity, mut flame) in flame_query.iter_mut() {
+        flame.lifetime.tick(time.delta());
+        if flame.lifetime.finished() {
+            commands.entity(flame_entity).despawn_recursive();
+        }
+    }
+}
+
+fn despawn_flames(mut commands: Commands, flame_query: Query<Entity, With<Flame>>) {
+    for flame_entity in flame_query.iter() {
+        commands.entity(flame_entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,194 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{ASSETS_FOLDER, LEVEL_MANIFEST_PATH},
+    map::{
+        asset::MapAsset, decorate::DecorationConfig, generate::GeneratorConfig, meta::BombRule,
+        CurrentMap,
+    },
+    GameState,
+};
+
+/// A level entry of the manifest, every path is relative to the assets folder.
+/// When `generator` is set the map is generated instead of loaded from `map`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LevelInfo {
+    pub name: String,
+    #[serde(default)]
+    pub map: String,
+    #[serde(default)]
+    pub generator: Option<GeneratorConfig>,
+    pub music: String,
+    pub game_over: String,
+    pub theme: String,
+    #[serde(default)]
+    pub decoration: Option<DecorationConfig>,
+    /// How the bombs of the player are counted, the map header can replace it
+    #[serde(default)]
+    pub bomb_rule: BombRule,
+}
+
+impl LevelInfo {
+    /// It returns the path of the map of the level, generated maps are named after their seed unless
+    /// the level sets a map path
+    pub fn map_path(&self) -> String {
+        match &self.generator {
+            Some(generator) if self.map.is_empty() => {
+                format!("maps/generated_{}.txt", generator.seed)
+            }
+            _ => self.map.clone(),
+        }
+    }
+}
+
+/// Ordered list of the levels that will be played
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct LevelManifest {
+    pub levels: Vec<LevelInfo>,
+}
+
+/// Index of the level being played inside the `LevelManifest`
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CurrentLevel(pub usize);
+
+#[derive(Component)]
+pub struct LevelNameText;
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        let manifest = LevelManifest::from_file(LEVEL_MANIFEST_PATH);
+        assert!(!manifest.levels.is_empty(), "Level manifest has no levels");
+
+        app.insert_resource(manifest)
+            .init_resource::<CurrentLevel>()
+            .add_system_set(SystemSet::on_enter(GameState::Gameplay).with_system(level_name_setup))
+            .add_system_set(
+                SystemSet::on_exit(GameState::Gameplay).with_system(despawn_level_name),
+            );
+    }
+}
+
+impl LevelManifest {
+    /// It reads and parses the level manifest
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: The path of the manifest file
+    ///
+    /// Returns:
+    ///
+    /// LevelManifest
+    pub fn from_file(path: &str) -> LevelManifest {
+        let text = fs::read_to_string(path).expect("No level manifest found");
+        ron::from_str(&text).expect("Invalid level manifest")
+    }
+
+    /// It returns the level at `current`, falling back to the first one
+    ///
+    /// Arguments:
+    ///
+    /// * `current`: The index of the level
+    ///
+    /// Returns:
+    ///
+    /// &LevelInfo
+    pub fn get(&self, current: &CurrentLevel) -> &LevelInfo {
+        self.levels.get(current.0).unwrap_or(&self.levels[0])
+    }
+
+    /// It returns the level after `current`, if there is one
+    ///
+    /// Arguments:
+    ///
+    /// * `current`: The index of the level that was finished
+    ///
+    /// Returns:
+    ///
+    /// Option<CurrentLevel>
+    pub fn next(&self, current: &CurrentLevel) -> Option<CurrentLevel> {
+        if current.0 + 1 < self.levels.len() {
+            Some(CurrentLevel(current.0 + 1))
+        } else {
+            None
+        }
+    }
+
+    /// It returns the bomb rule a map is played with, the one of its header or the one of the level
+    /// that plays it. Maps of no level use the default rule
+    ///
+    /// Arguments:
+    ///
+    /// * `map_path`: The path of the map file, relative to the assets folder or the working directory
+    /// * `map`: The parsed map
+    ///
+    /// Returns:
+    ///
+    /// BombRule
+    pub fn bomb_rule(&self, map_path: &str, map: &MapAsset) -> BombRule {
+        let map_path = map_path
+            .strip_prefix(&format!("{}/", ASSETS_FOLDER))
+            .unwrap_or(map_path);
+        let level_rule = self
+            .levels
+            .iter()
+            .find(|level| level.map_path() == map_path)
+            .map(|level| level.bomb_rule)
+            .unwrap_or_default();
+        map.meta.bomb_rule.unwrap_or(level_rule)
+    }
+}
+
+/// It shows the name of the current level at the top left of the screen
+///
+/// Arguments:
+///
+/// * `commands`: Commands
+/// * `asset_server`: Res<AssetServer>
+/// * `manifest`: Res<LevelManifest>
+/// * `current_level`: Res<CurrentLevel>
+/// * `maps`: Res<Assets<MapAsset>>
+/// * `current_map`: Res<CurrentMap> - A map header can replace the name of the level
+fn level_name_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    manifest: Res<LevelManifest>,
+    current_level: Res<CurrentLevel>,
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
+) {
+    let name = current_map
+        .meta(&maps)
+        .name
+        .unwrap_or_else(|| manifest.get(&current_level).name.clone());
+    commands.spawn((
+        TextBundle::from_section(
+            name,
+            TextStyle {
+                font: asset_server.load("fonts/Kenney-Future.ttf"),
+                font_size: 30.0,
+                color: Color::BLACK,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(5.0),
+                left: Val::Px(15.0),
+                ..default()
+            },
+            ..default()
+        }),
+        LevelNameText,
+    ));
+}
+
+fn despawn_level_name(mut commands: Commands, text_query: Query<Entity, With<LevelNameText>>) {
+    for text in text_query.iter() {
+        commands.entity(text).despawn_recursive();
+    }
+}
@@ -0,0 +1,25 @@
+pub mod audio;
+pub mod bomb;
+pub mod collider;
+pub mod constants;
+pub mod editor;
+pub mod flame;
+pub mod gameover;
+pub mod level;
+pub mod logic;
+pub mod map;
+pub mod menu;
+pub mod nextlevel;
+pub mod player;
+pub mod solver;
+pub mod utils;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum GameState {
+    Menu,
+    Loading,
+    Gameplay,
+    GameOver,
+    NextLevel,
+    Editor,
+}
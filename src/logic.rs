@@ -1,11 +1,16 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
 use bevy_rapier3d::prelude::{ActiveCollisionTypes, ActiveEvents, Collider, RigidBody, Sensor};
 
 use crate::{
     bomb::Bomb,
     collider::UpgradeType,
     constants::DEFAULT_OBJECT_SCALE,
-    map::{Breakable, CustomProps, ObjectProps},
+    map::{
+        asset::MapAsset,
+        hidden::HiddenItem,
+        meta::{BombRule, MapMeta, WinCondition},
+        Breakable, CurrentMap, CustomProps, ObjectProps,
+    },
     player::Player,
     utils::spawn_custom,
     GameState,
@@ -16,16 +21,164 @@ pub struct GameLogicPlugin;
 #[derive(Component)]
 pub struct Flag;
 
+#[derive(Component)]
+pub struct TimeLimitText;
+
+/// Header of the map being played, how its bombs are counted, where its flags appear, what its
+/// breakables hide and the countdown of its time limit, if it has one
+#[derive(Resource, Default)]
+pub struct LevelRules {
+    pub meta: MapMeta,
+    pub bomb_rule: BombRule,
+    pub exit_positions: Vec<Vec3>,
+    pub hidden: HashMap<IVec2, HiddenItem>,
+    pub time_left: Option<Timer>,
+}
+
 impl Plugin for GameLogicPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::on_update(GameState::Gameplay)
-                .with_system(has_finalized)
-                .with_system(has_lose),
-        );
+        app.init_resource::<LevelRules>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Gameplay)
+                    .with_system(setup_level_rules)
+                    .with_system(time_limit_setup),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Gameplay)
+                    .with_system(has_finalized)
+                    .with_system(has_lose)
+                    .with_system(tick_time_limit),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Gameplay).with_system(despawn_time_limit),
+            );
+    }
+}
+
+impl LevelRules {
+    /// It reads the rules of the level from the header, the exit points and the hidden items of a map
+    ///
+    /// Arguments:
+    ///
+    /// * `map`: The loaded map
+    /// * `current_map`: The map of the level, it sets the bomb rule the header doesn't replace
+    ///
+    /// Returns:
+    ///
+    /// LevelRules
+    pub fn new(map: &MapAsset, current_map: &CurrentMap) -> LevelRules {
+        LevelRules {
+            meta: map.meta.clone(),
+            bomb_rule: current_map.bomb_rule(map),
+            exit_positions: map.exit_positions(),
+            hidden: map.hidden.clone(),
+            time_left: map
+                .meta
+                .time_limit
+                .map(|seconds| Timer::from_seconds(seconds, TimerMode::Once)),
+        }
     }
 }
-/// If there are no more breakable objects and no more flags, then spawn a flag at the end of the level
+
+/// It reads the rules of the level from the current map
+///
+/// Arguments:
+///
+/// * `maps`: Res<Assets<MapAsset>>
+/// * `current_map`: Res<CurrentMap>
+/// * `rules`: ResMut<LevelRules>
+fn setup_level_rules(
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
+    mut rules: ResMut<LevelRules>,
+) {
+    let map = maps.get(&current_map.handle).expect("Map is not loaded");
+    *rules = LevelRules::new(map, &current_map);
+}
+
+/// It shows the time left at the top right of the screen when the level has a time limit
+///
+/// Arguments:
+///
+/// * `commands`: Commands
+/// * `asset_server`: Res<AssetServer>
+/// * `maps`: Res<Assets<MapAsset>>
+/// * `current_map`: Res<CurrentMap>
+fn time_limit_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
+) {
+    let time_limit = match current_map.meta(&maps).time_limit {
+        Some(time_limit) => time_limit,
+        None => return,
+    };
+    commands.spawn((
+        TextBundle::from_section(
+            format!("{:.0}", time_limit.ceil()),
+            TextStyle {
+                font: asset_server.load("fonts/Kenney-Future.ttf"),
+                font_size: 30.0,
+                color: Color::BLACK,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(5.0),
+                right: Val::Px(15.0),
+                ..default()
+            },
+            ..default()
+        }),
+        TimeLimitText,
+    ));
+}
+
+/// It counts down the time limit of the level. When it runs out the player wins a survival level
+/// and loses any other
+///
+/// Arguments:
+///
+/// * `time`: Res<Time>
+/// * `rules`: ResMut<LevelRules>
+/// * `text_query`: Query<&mut Text, With<TimeLimitText>>
+/// * `game_state`: ResMut<State<GameState>>
+fn tick_time_limit(
+    time: Res<Time>,
+    mut rules: ResMut<LevelRules>,
+    mut text_query: Query<&mut Text, With<TimeLimitText>>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    let win_condition = rules.meta.win_condition();
+    let timer = match rules.time_left.as_mut() {
+        Some(timer) => timer,
+        None => return,
+    };
+    timer.tick(time.delta());
+
+    let seconds_left = timer.duration().as_secs_f32() - timer.elapsed_secs();
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("{:.0}", seconds_left.ceil());
+    }
+
+    if timer.just_finished() {
+        //The state may already be changing if the level ended this frame
+        let _ = match win_condition {
+            WinCondition::Survive => game_state.set(GameState::NextLevel),
+            WinCondition::ClearBreakables => game_state.set(GameState::GameOver),
+        };
+    }
+}
+
+fn despawn_time_limit(mut commands: Commands, text_query: Query<Entity, With<TimeLimitText>>) {
+    for text in text_query.iter() {
+        commands.entity(text).despawn_recursive();
+    }
+}
+/// If there are no more breakable objects and no more flags, then spawn a flag on each exit point of
+/// the level. Survival levels never spawn the flag, they end with the time limit
 ///
 /// Arguments:
 ///
@@ -34,62 +187,84 @@ impl Plugin for GameLogicPlugin {
 /// * `breakable_query`: Query<(Entity, &Breakable, &Transform), Without<Flag>>
 /// * `flag_query`: Query<(Entity, &Flag, &Transform), Without<Breakable>>,
 /// * `asset_server`: Res<AssetServer>
+/// * `rules`: Res<LevelRules>
 fn has_finalized(
     mut commands: Commands,
     breakable_query: Query<(Entity, &Breakable, &Transform), Without<Flag>>,
     flag_query: Query<(Entity, &Flag, &Transform), Without<Breakable>>,
     asset_server: Res<AssetServer>,
+    rules: Res<LevelRules>,
 ) {
+    if rules.meta.win_condition() != WinCondition::ClearBreakables {
+        return;
+    }
     if breakable_query.is_empty() && flag_query.is_empty() {
-        let object_props = ObjectProps {
-            add_floor: false,
-            is_floor: true,
-            upgrade: UpgradeType::None,
-            path: "objects/flag.glb#Scene0".to_owned(),
-            custom: Some(CustomProps {
-                scale: DEFAULT_OBJECT_SCALE.to_owned(),
-                rotation: Quat::from_rotation_y(45.0),
-                sum_translation: Vec3::ZERO,
-            }),
-            animated_rotation: false,
-            breakable: false,
-            name: String::from("EndFlag"),
-        };
-        let flag = spawn_custom(
-            &mut commands,
-            &object_props,
-            &asset_server,
-            Vec3::new(0.1, 0.2, -0.1),
-        );
-        commands
-            .entity(flag)
-            .insert(Flag)
-            .insert(Sensor)
-            .insert(ActiveCollisionTypes::KINEMATIC_STATIC)
-            .insert(ActiveEvents::COLLISION_EVENTS)
-            .insert(Collider::cuboid(0.3, 0.3, 0.3))
-            .insert(RigidBody::Fixed);
+        for exit_position in rules.exit_positions.iter() {
+            spawn_flag(&mut commands, &asset_server, *exit_position);
+        }
     }
 }
 
+/// It spawns the end flag, touching it finishes the level
+///
+/// Arguments:
+///
+/// * `commands`: &mut Commands
+/// * `asset_server`: &AssetServer
+/// * `translation`: The position of the flag
+fn spawn_flag(commands: &mut Commands, asset_server: &AssetServer, translation: Vec3) {
+    let object_props = ObjectProps {
+        add_floor: false,
+        is_floor: true,
+        upgrade: UpgradeType::None,
+        path: "objects/flag.glb#Scene0".to_owned(),
+        custom: Some(CustomProps {
+            scale: DEFAULT_OBJECT_SCALE.to_owned(),
+            rotation: Quat::from_rotation_y(45.0),
+            sum_translation: Vec3::ZERO,
+        }),
+        animated_rotation: false,
+        breakable: false,
+        name: String::from("EndFlag"),
+        autotile: None,
+        hits: None,
+        damaged_paths: Vec::new(),
+    };
+    let flag = spawn_custom(commands, &object_props, asset_server, translation);
+    commands
+        .entity(flag)
+        .insert(Flag)
+        .insert(Sensor)
+        .insert(ActiveCollisionTypes::KINEMATIC_STATIC)
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(Collider::cuboid(0.3, 0.3, 0.3))
+        .insert(RigidBody::Fixed);
+}
+
 /// If there are breakables left, the player has no bombs, and there are no bombs on the map, the player
-/// loses
+/// loses. Bombs always come back with the capacity rule, so it only applies to consumable bombs
 ///
 /// Arguments:
 ///
 /// * `breakable_query`: Query<&Breakable, Without<Player>>
 /// * `bomb_query`: Query<&Bomb, Without<Player>>
 /// * `player_query`: Query<&Player, With<Player>>
+/// * `rules`: Res<LevelRules>
 /// * `game_state`: ResMut<State<GameState>>
 fn has_lose(
     breakable_query: Query<&Breakable, Without<Player>>,
     bomb_query: Query<&Bomb, Without<Player>>,
     mut player_query: Query<&Player, With<Player>>,
+    rules: Res<LevelRules>,
     mut game_state: ResMut<State<GameState>>,
 ) {
     let player = player_query.single_mut();
 
-    if !breakable_query.is_empty() && player.bomb_amount == 0 && bomb_query.is_empty() {
+    if rules.bomb_rule == BombRule::Consumable
+        && !breakable_query.is_empty()
+        && player.bomb_amount == 0
+        && bomb_query.is_empty()
+    {
         println!("YOU LOSE");
         game_state.set(GameState::GameOver);
     }
@@ -1,6 +1,5 @@
 use std::f32::consts::PI;
 
-use audio::GameAudioPlugin;
 use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
 use bevy_inspector_egui::WorldInspectorPlugin;
@@ -8,77 +7,66 @@ use bevy_rapier3d::{
     prelude::{NoUserData, RapierPhysicsPlugin},
     render::RapierDebugRenderPlugin,
 };
-use bomb::BombPlugin;
-use collider::ColliderPlugin;
-use constants::{HEIGHT, WIDTH};
-use gameover::GameOverPlugin;
-use logic::GameLogicPlugin;
-use map::MapPlugin;
-use menu::MenuPlugin;
-use nextlevel::NextLevelPlugin;
-use player::PlayerPlugin;
+use minigame2::{
+    audio::GameAudioPlugin,
+    bomb::BombPlugin,
+    collider::ColliderPlugin,
+    constants::{HEIGHT, WIDTH},
+    editor::EditorPlugin,
+    flame::FlamePlugin,
+    gameover::GameOverPlugin,
+    level::LevelPlugin,
+    logic::GameLogicPlugin,
+    map::MapPlugin,
+    menu::MenuPlugin,
+    nextlevel::NextLevelPlugin,
+    player::PlayerPlugin,
+    GameState,
+};
 use serde::__private::de;
 use simula_action::ActionPlugin;
 use simula_camera::{flycam::*, orbitcam::*};
 
-pub mod audio;
-pub mod bomb;
-pub mod collider;
-pub mod constants;
-pub mod gameover;
-pub mod logic;
-pub mod map;
-pub mod menu;
-pub mod nextlevel;
-pub mod player;
-pub mod utils;
-
 #[derive(Component)]
 pub struct SunLight;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
-pub enum GameState {
-    Menu,
-    Loading,
-    Gameplay,
-    GameOver,
-    NextLevel,
-}
-
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
-pub enum Level {
-    Level1,
-    Level2,
-    Level3,
-}
-
 fn main() {
     let mut app = App::new();
 
     app.insert_resource(ClearColor(Color::rgb(255., 255., 255.)))
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            window: WindowDescriptor {
-                width: WIDTH,
-                height: HEIGHT,
-                title: "BomberBevy".to_string(),
-                resizable: false,
-                ..default()
-            },
-            ..default()
-        }))
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    window: WindowDescriptor {
+                        width: WIDTH,
+                        height: HEIGHT,
+                        title: "BomberBevy".to_string(),
+                        resizable: false,
+                        ..default()
+                    },
+                    ..default()
+                })
+                //Reload the maps when they change on disk
+                .set(AssetPlugin {
+                    watch_for_changes: true,
+                    ..default()
+                }),
+        )
         //Game State
         .add_state(GameState::Menu)
-        .add_state(Level::Level1)
         //Custom Mod Import
+        .add_plugin(LevelPlugin)
         .add_plugin(MapPlugin)
         .add_plugin(GameAudioPlugin)
         .add_plugin(GameLogicPlugin)
         .add_plugin(PlayerPlugin)
         .add_plugin(BombPlugin)
+        .add_plugin(FlamePlugin)
         .add_plugin(ColliderPlugin)
         .add_plugin(MenuPlugin)
         .add_plugin(GameOverPlugin)
         .add_plugin(NextLevelPlugin)
+        .add_plugin(EditorPlugin)
         //External Mod Import
         .add_plugin(EguiPlugin)
         .add_plugin(ActionPlugin)
@@ -92,6 +80,10 @@ fn main() {
         .add_system_set(
             SystemSet::on_exit(GameState::Gameplay).with_system(despawn_setup_basic_scene),
         )
+        .add_system_set(SystemSet::on_enter(GameState::Editor).with_system(setup_basic_scene))
+        .add_system_set(
+            SystemSet::on_exit(GameState::Editor).with_system(despawn_setup_basic_scene),
+        )
         .add_startup_system(spawn_camera)
         .run();
 }
@@ -1,16 +1,50 @@
-use serde::{Deserialize, Serialize};
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use bevy::{asset::LoadState, prelude::*};
 use bevy_inspector_egui::Inspectable;
 
 use crate::{
-    collider::UpgradeType,
-    utils::{spawn_custom, spawn_floor, spawn_object, MapObject},
-    GameState, Level,
+    bomb::Bomb,
+    collider::{ClearedCells, UpgradeType},
+    constants::{ASSETS_FOLDER, MAP_EXPORT_FOLDER, THEMES_PATH},
+    level::{CurrentLevel, LevelInfo, LevelManifest},
+    logic::{Flag, LevelRules},
+    player::Player,
+    utils::{spawn_custom, spawn_decoration, spawn_floor, spawn_object, MapObject},
+    GameState,
+};
+
+pub mod asset;
+pub mod autotile;
+pub mod decorate;
+pub mod export;
+pub mod generate;
+pub mod grid;
+pub mod hidden;
+pub mod meta;
+pub mod palette;
+pub mod theme;
+pub mod tiled;
+pub mod validate;
+
+use self::{
+    asset::{MapAsset, MapAssetLoader},
+    autotile::{autotile, autotile_floor, AutotileSet},
+    decorate::{scatter, DecorationConfig},
+    export::export_map,
+    generate::generate,
+    grid::{CellContent, GridMap},
+    meta::{BombRule, MapMeta},
+    palette::{load_level_palette, MapPalette, PaletteError},
+    theme::{Theme, ThemeSet},
+    tiled::TiledMapLoader,
+    validate::palette_issues,
 };
 
 pub struct MapPlugin;
@@ -18,11 +52,105 @@ pub struct MapPlugin;
 #[derive(Component, Inspectable)]
 pub struct Breakable;
 
+/// Explosions a breakable takes before it is destroyed, its scene changes with every hit it takes
+#[derive(Component, Clone, Debug)]
+pub struct Health {
+    pub hits: u32,
+    pub max_hits: u32,
+    /// Scenes of the damaged breakable, the first one after the first hit and so on
+    pub damaged_paths: Vec<String>,
+}
+
+impl Health {
+    pub fn new(object_props: &ObjectProps) -> Health {
+        Health {
+            hits: object_props.hits(),
+            max_hits: object_props.hits(),
+            damaged_paths: object_props.damaged_paths.clone(),
+        }
+    }
+
+    /// It takes a hit and returns if the breakable is destroyed
+    pub fn hit(&mut self) -> bool {
+        self.hits = self.hits.saturating_sub(1);
+        self.hits == 0
+    }
+
+    /// It returns the scene for the hits taken, breakables with fewer damaged scenes than hits keep
+    /// the last one. `None` keeps the current scene
+    pub fn damaged_path(&self) -> Option<&str> {
+        let hits_taken = (self.max_hits - self.hits) as usize;
+        if hits_taken == 0 {
+            return None;
+        }
+        self.damaged_paths
+            .get(hits_taken - 1)
+            .or_else(|| self.damaged_paths.last())
+            .map(String::as_str)
+    }
+}
+
 #[derive(Component, Inspectable)]
 pub struct AnimatedRotation;
 
 #[derive(Component, Inspectable)]
 pub struct ObjectCollider {}
+
+/// Map asset of the level being played, `path` is relative to the assets folder.
+/// `theme` is the theme of the level, the map header can replace it
+#[derive(Resource, Default)]
+pub struct CurrentMap {
+    pub handle: Handle<MapAsset>,
+    pub path: String,
+    pub theme: String,
+    pub decoration: Option<DecorationConfig>,
+    pub bomb_rule: BombRule,
+}
+
+impl CurrentMap {
+    /// It takes the map path, theme, decorations and bomb rule of the level, the handle is set apart
+    pub fn set_level(&mut self, level: &LevelInfo) {
+        self.path = level.map_path();
+        self.theme = level.theme.clone();
+        self.decoration = level.decoration.clone();
+        self.bomb_rule = level.bomb_rule;
+    }
+
+    /// It returns the header of the current map, or the default one if the map is not loaded
+    ///
+    /// Arguments:
+    ///
+    /// * `maps`: The loaded maps
+    ///
+    /// Returns:
+    ///
+    /// MapMeta
+    pub fn meta(&self, maps: &Assets<MapAsset>) -> MapMeta {
+        maps.get(&self.handle)
+            .map(|map| map.meta.clone())
+            .unwrap_or_default()
+    }
+
+    /// It returns the theme of the map, the one of its header or the one of the level
+    ///
+    /// Arguments:
+    ///
+    /// * `map`: The loaded map
+    /// * `themes`: Every theme of the game
+    ///
+    /// Returns:
+    ///
+    /// Theme
+    pub fn theme(&self, map: &MapAsset, themes: &ThemeSet) -> Theme {
+        themes.get(map.meta.theme.as_deref().unwrap_or(&self.theme))
+    }
+
+    /// It returns the bomb rule of the map, the one of its header or the one of the level
+    pub fn bomb_rule(&self, map: &MapAsset) -> BombRule {
+        map.meta.bomb_rule.unwrap_or(self.bomb_rule)
+    }
+}
+
 #[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub struct CustomProps {
     pub scale: Vec3,
@@ -39,11 +167,43 @@ pub struct ObjectProps {
     pub animated_rotation: bool,
     pub breakable: bool,
     pub custom: Option<CustomProps>,
+    /// Pieces used when the tile is drawn as a connected path
+    #[serde(default)]
+    pub autotile: Option<AutotileSet>,
+    /// Explosions a breakable takes before it is destroyed, one if it is not set
+    #[serde(default)]
+    pub hits: Option<u32>,
+    /// Scenes the breakable changes to as it takes hits
+    #[serde(default)]
+    pub damaged_paths: Vec<String>,
+}
+
+impl ObjectProps {
+    /// It returns the explosions the object takes before it is destroyed
+    pub fn hits(&self) -> u32 {
+        self.hits.unwrap_or(1).max(1)
+    }
 }
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Gameplay).with_system(create_basic_map))
+        let themes = ThemeSet::from_file(THEMES_PATH);
+
+        app.insert_resource(themes)
+            .add_asset::<MapAsset>()
+            .init_asset_loader::<MapAssetLoader>()
+            .init_asset_loader::<TiledMapLoader>()
+            .init_resource::<CurrentMap>()
+            .init_resource::<GridMap>()
+            .init_resource::<MapPalette>()
+            .add_system_set(SystemSet::on_enter(GameState::Loading).with_system(load_current_map))
+            .add_system_set(SystemSet::on_update(GameState::Loading).with_system(wait_for_map))
+            .add_system_set(SystemSet::on_enter(GameState::Gameplay).with_system(create_basic_map))
+            .add_system_set(
+                SystemSet::on_update(GameState::Gameplay)
+                    .with_system(reload_map)
+                    .with_system(export_current_map),
+            )
             .add_system_set(SystemSet::on_exit(GameState::Gameplay).with_system(despawn_map));
     }
 }
@@ -54,26 +214,76 @@ impl Plugin for MapPlugin {
 ///
 /// * `commands`: The commands that will be used to spawn the object.
 /// * `asset_server`: The asset server that we created in the previous section.
-/// * `_object_types`: A HashMap of all the object types that can be spawned.
+/// * `_object_types`: The palette of all the object types that can be spawned.
 /// * `char_key`: The key of the object to spawn.
+/// * `floor`: The tile of the floor layer on the cell, if the map has one there
 /// * `translation`: The position of the object
 ///
 /// Returns:
 ///
-/// Entity
+/// The object, or None if the key is not in the palette
 pub fn spawn_map_object(
     commands: &mut Commands,
     asset_server: &AssetServer,
-    _object_types: &HashMap<i32, ObjectProps>,
-    char_key: i32,
+    _object_types: &MapPalette,
+    char_key: char,
+    floor: Option<&ObjectProps>,
     translation: Vec3,
-) -> Entity {
-    let object_props = _object_types.get(&char_key).unwrap();
+) -> Option<Entity> {
+    let object_props = match _object_types.get(char_key) {
+        Some(object_props) => object_props,
+        None => {
+            warn!("Unknown map character {:?} was not spawned", char_key);
+            return None;
+        }
+    };
+    Some(spawn_props(
+        commands,
+        asset_server,
+        _object_types,
+        object_props,
+        floor,
+        translation,
+    ))
+}
 
+/// It spawns the object described by `object_props`, with its floor if it needs one. The tile of
+/// the floor layer replaces the default floor under objects and the floor tiles of the object layer
+///
+/// Arguments:
+///
+/// * `commands`: The commands that will be used to spawn the object.
+/// * `asset_server`: The asset server
+/// * `palette`: The palette of the map, it sets the default floor
+/// * `object_props`: The object to spawn
+/// * `floor`: The tile of the floor layer on the cell, if the map has one there
+/// * `translation`: The position of the object
+///
+/// Returns:
+///
+/// Entity
+pub fn spawn_props(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    palette: &MapPalette,
+    object_props: &ObjectProps,
+    floor: Option<&ObjectProps>,
+    translation: Vec3,
+) -> Entity {
     //If floor is needed , spawn floor and the object
     if object_props.add_floor {
-        //Spawn default floor
-        spawn_floor(commands, object_props, asset_server, translation, true);
+        match floor {
+            //Spawn the floor of the floor layer
+            Some(floor) => spawn_floor(commands, floor, asset_server, translation, None),
+            //Spawn default floor
+            None => spawn_floor(
+                commands,
+                object_props,
+                asset_server,
+                translation,
+                Some(palette.default_floor()),
+            ),
+        };
         if object_props.custom.is_some() {
             return spawn_custom(commands, object_props, asset_server, translation);
         }
@@ -82,180 +292,448 @@ pub fn spawn_map_object(
     }
     //If is a floor, custom collider
     if object_props.is_floor {
-        //Spawn custom Floor
-        return spawn_floor(commands, object_props, asset_server, translation, false);
+        //Spawn custom Floor, the floor layer wins over the object layer
+        return spawn_floor(
+            commands,
+            floor.unwrap_or(object_props),
+            asset_server,
+            translation,
+            None,
+        );
+    }
+    if object_props.custom.is_some() {
+        return spawn_custom(commands, object_props, asset_server, translation);
     }
     //Spawn Object
     return spawn_object(commands, object_props, asset_server, translation);
 }
-/// It reads a text file and spawns the objects in the scene based on the characters in the text file
+/// It starts loading the map of the current level through the asset server, or generates it if the
+/// level has a generator
+///
+/// Arguments:
+///
+/// * `asset_server`: Res<AssetServer> - This is the asset server that we will use to load the map.
+/// * `maps`: ResMut<Assets<MapAsset>> - Where the generated maps are added
+/// * `manifest`: Res<LevelManifest> - The list of levels of the game
+/// * `current_level`: Res<CurrentLevel> - The level whose map will be loaded
+/// * `current_map`: ResMut<CurrentMap> - Where the handle of the map is stored
+/// * `game_state`: ResMut<State<GameState>> - Goes back to the menu if the map can't be generated
+fn load_current_map(
+    asset_server: Res<AssetServer>,
+    mut maps: ResMut<Assets<MapAsset>>,
+    manifest: Res<LevelManifest>,
+    current_level: Res<CurrentLevel>,
+    mut current_map: ResMut<CurrentMap>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    let level = manifest.get(&current_level);
+    current_map.set_level(level);
+    current_map.handle = match &level.generator {
+        Some(generator) => match generate(generator, MapAsset::default().spawn_cell()) {
+            Ok(map) => maps.add(map),
+            Err(error) => {
+                error!("Could not generate the map of {}: {}", level.name, error);
+                //Never start the gameplay with the map of the previous level
+                current_map.handle = Handle::default();
+                game_state.set(GameState::Menu).unwrap();
+                return;
+            }
+        },
+        None => asset_server.load(current_map.path.as_str()),
+    };
+}
+
+/// It starts the gameplay once the map is loaded, or goes back to the menu if the map failed to load
+/// or can't be spawned with its palette
+///
+/// Arguments:
+///
+/// * `asset_server`: Res<AssetServer>
+/// * `maps`: Res<Assets<MapAsset>>
+/// * `current_map`: Res<CurrentMap>
+/// * `themes`: Res<ThemeSet>
+/// * `game_state`: ResMut<State<GameState>>
+fn wait_for_map(
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
+    themes: Res<ThemeSet>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    //Generated maps are added directly, they never have a load state
+    if let Some(map) = maps.get(&current_map.handle) {
+        match current_palette(&current_map, map, &themes) {
+            Ok(_) => game_state.set(GameState::Gameplay).unwrap(),
+            Err(error) => {
+                error!("Could not spawn {}: {}", current_map.path, error);
+                game_state.set(GameState::Menu).unwrap();
+            }
+        }
+        return;
+    }
+    if asset_server.get_load_state(&current_map.handle) == LoadState::Failed {
+        error!("Could not load the map of the current level");
+        game_state.set(GameState::Menu).unwrap();
+    }
+}
+
+/// It spawns the loaded map of the current level and fills the logical map
 ///
 /// Arguments:
 ///
 /// * `commands`: Commands,
 /// * `asset_server`: Res<AssetServer> - This is the asset server that we will use to load the assets.
-
+/// * `maps`: Res<Assets<MapAsset>> - The loaded maps
+/// * `current_map`: Res<CurrentMap> - The map of the level that will be spawned
+/// * `themes`: Res<ThemeSet> - The themes the palette paths are resolved through
+/// * `palette`: ResMut<MapPalette> - The palette of the level, loaded here
+/// * `grid_map`: ResMut<GridMap> - The logical map that will be filled with the spawned objects
+/// * `game_state`: ResMut<State<GameState>> - Goes back to the menu if the palette is invalid
+#[allow(clippy::too_many_arguments)]
 fn create_basic_map(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    level_state: Res<State<Level>>,
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
+    themes: Res<ThemeSet>,
+    mut palette: ResMut<MapPalette>,
+    mut grid_map: ResMut<GridMap>,
+    mut game_state: ResMut<State<GameState>>,
 ) {
-    let mut file = File::open("assets/maps/level1.txt").expect("No map found");
-    if level_state.current().to_owned() == Level::Level1 {
-        file = File::open("assets/maps/level1.txt").expect("No map found");
-    }
-    if level_state.current().to_owned() == Level::Level2 {
-        file = File::open("assets/maps/level2.txt").expect("No map found");
-    }
-    if level_state.current().to_owned() == Level::Level3 {
-        file = File::open("assets/maps/level3.txt").expect("No map found");
+    let map = maps.get(&current_map.handle).expect("Map is not loaded");
+    let theme = current_map.theme(map, &themes);
+
+    //Palette that maps each character and relates to the rendering
+    *palette = match current_palette(&current_map, map, &themes) {
+        Ok(palette) => palette,
+        Err(error) => {
+            error!("Could not spawn {}: {}", current_map.path, error);
+            let _ = game_state.set(GameState::Menu);
+            return;
+        }
+    };
+    *grid_map = spawn_map(&mut commands, &asset_server, map, &palette);
+    if let Some(decoration) = &current_map.decoration {
+        decorate_map(
+            &mut commands,
+            &asset_server,
+            map,
+            &grid_map,
+            decoration,
+            &palette,
+            &theme,
+        );
     }
+}
 
-    //Hashmap that maps each character index and relates to the rendering
-    let object_types = HashMap::from([
-        (
-            32,
-            ObjectProps {
-                add_floor: false,
-                is_floor: true,
-                upgrade: UpgradeType::None,
-                path: "objects/tile.glb#Scene0".to_owned(),
-                custom: None,
-                animated_rotation: false,
-                breakable: false,
-                name: String::from("Floor"),
-            },
-        ), //Floor
-        (
-            87,
-            ObjectProps {
-                add_floor: false,
-                is_floor: false,
-                upgrade: UpgradeType::None,
-                path: "objects/towerSquare_sampleE.glb#Scene0".to_owned(),
-                custom: None,
-                animated_rotation: false,
-                breakable: false,
-                name: String::from("Wall"),
-            },
-        ), //Wall
-        (
-            66,
-            ObjectProps {
-                add_floor: true,
-                is_floor: false,
-                upgrade: UpgradeType::None,
-                path: "objects/towerSquare_middleA.glb#Scene0".to_owned(),
-                custom: None,
-                animated_rotation: false,
-                breakable: false,
-                name: String::from("Block"),
-            },
-        ), //Block
-        (
-            67,
-            ObjectProps {
-                add_floor: true,
-                is_floor: false,
-                upgrade: UpgradeType::Bomb,
-                path: "objects/bombup.glb#Scene0".to_owned(),
-                custom: Some(CustomProps {
-                    scale: Vec3::new(0.2, 0.3, 0.2),
-                    rotation: Quat::from_rotation_y(0.0),
-                    sum_translation: Vec3::new(0.0, 0.5, 0.0),
-                }),
-                animated_rotation: true,
-                breakable: true,
-                name: String::from("BombUp"),
-            },
-        ), //Coin
-        (
-            82,
-            ObjectProps {
-                add_floor: true,
-                is_floor: false,
-                upgrade: UpgradeType::None,
-                path: "objects/towerSquare_sampleE.glb#Scene0".to_owned(),
-                custom: None,
-                animated_rotation: false,
-                breakable: false,
-                name: String::from("RoundedWall"),
-            },
-        ), //Rounded Wall
-        (
-            35,
-            ObjectProps {
-                add_floor: false,
-                is_floor: false,
-                upgrade: UpgradeType::None,
-                path: "objects/towerSquare_sampleA.glb#Scene0".to_owned(),
-                custom: None,
-                animated_rotation: false,
-                breakable: false,
-                name: String::from("TowerA"),
-            },
-        ), //TowerA
-        (
-            36,
-            ObjectProps {
-                add_floor: false,
-                is_floor: false,
-                upgrade: UpgradeType::None,
-                path: "objects/towerSquare_sampleE.glb#Scene0".to_owned(),
-                custom: None,
-                animated_rotation: false,
-                breakable: false,
-                name: String::from("TowerC"),
-            },
-        ), //TowerC
-        (
-            37,
-            ObjectProps {
-                add_floor: false,
-                is_floor: true,
-                upgrade: UpgradeType::None,
-                path: "objects/tile_straight.glb#Scene0".to_owned(),
-                custom: None,
-                animated_rotation: false,
-                breakable: false,
-                name: String::from("FloorStraight"),
-            },
-        ), //FloorStraight
-        (
-            64,
-            ObjectProps {
-                add_floor: true,
-                is_floor: false,
-                upgrade: UpgradeType::None,
-                path: "objects/workbench.glb#Scene0".to_owned(),
-                custom: Some(CustomProps {
-                    scale: Vec3::new(0.5, 0.5, 0.6),
-                    rotation: Quat::from_rotation_y(0.0),
-                    sum_translation: Vec3::new(0.0, 0.2, 0.0),
-                }),
-                animated_rotation: false,
-                breakable: true,
-                name: String::from("Workbench"),
-            },
-        ), //FloorStraight
-    ]);
-    for (z, line) in BufReader::new(file).lines().enumerate() {
-        if let Ok(line) = line {
-            for (x, char) in line.chars().enumerate() {
-                spawn_map_object(
-                    &mut commands,
-                    &asset_server,
-                    &object_types,
-                    char as i32,
-                    Vec3::new(x as f32 / 2. - 6., 0.0, z as f32 / 2. - 4.),
+/// It spawns every object of the map and returns the logical map with what was spawned
+///
+/// Arguments:
+///
+/// * `commands`: &mut Commands,
+/// * `asset_server`: &AssetServer - This is the asset server that we will use to load the assets.
+/// * `map`: The map to spawn
+/// * `palette`: The palette that relates each character with an object
+///
+/// Returns:
+///
+/// GridMap
+pub fn spawn_map(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    map: &MapAsset,
+    palette: &MapPalette,
+) -> GridMap {
+    //Paths are drawn with the piece that connects to their neighbours
+    let autotiles = autotile(map, palette);
+    let floor_autotiles = autotile_floor(map, palette);
+    for (cell, char) in map.cells() {
+        let translation = GridMap::cell_to_world(cell);
+        let floor = floor_autotiles
+            .get(&cell)
+            .or_else(|| map.floor_at(cell).and_then(|floor| palette.get(floor)));
+        match autotiles.get(&cell) {
+            Some(object_props) => {
+                spawn_props(
+                    commands,
+                    asset_server,
+                    palette,
+                    object_props,
+                    floor,
+                    translation,
                 );
             }
+            None => {
+                spawn_map_object(commands, asset_server, palette, char, floor, translation);
+            }
         }
     }
+    GridMap::from_map(map, palette)
+}
+
+/// It spawns the decorations of the level around the map and on the floor out of play
+///
+/// Arguments:
+///
+/// * `commands`: &mut Commands,
+/// * `asset_server`: &AssetServer
+/// * `map`: The map of the level
+/// * `grid_map`: The logical map of the spawned map
+/// * `config`: The decoration settings of the level
+/// * `palette`: The palette of the level, its default floor is drawn under the decorations outside the map
+/// * `theme`: The theme the details are resolved through
+pub fn decorate_map(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    map: &MapAsset,
+    grid_map: &GridMap,
+    config: &DecorationConfig,
+    palette: &MapPalette,
+    theme: &Theme,
+) {
+    for decoration in scatter(config, grid_map, map.spawn_cell()) {
+        let translation = GridMap::cell_to_world(decoration.cell);
+        if decoration.ground {
+            spawn_decoration(
+                commands,
+                asset_server,
+                palette.default_floor(),
+                translation,
+                0.0,
+            );
+        }
+        if let Some(detail) = &decoration.detail {
+            spawn_decoration(
+                commands,
+                asset_server,
+                &theme.resolve(detail),
+                translation + Vec3::Y * 0.1,
+                decoration.rotation,
+            );
+        }
+    }
+}
+
+/// It loads the palette of the current map and checks that every character of the map can be
+/// spawned with it
+///
+/// Arguments:
+///
+/// * `current_map`: The map of the level, its path and theme pick the palette
+/// * `map`: The loaded map
+/// * `themes`: Every theme of the game
+///
+/// Returns:
+///
+/// The palette, or the first error found
+pub fn current_palette(
+    current_map: &CurrentMap,
+    map: &MapAsset,
+    themes: &ThemeSet,
+) -> Result<MapPalette, String> {
+    let palette = load_palette(
+        &format!("{}/{}", ASSETS_FOLDER, current_map.path),
+        map,
+        &current_map.theme(map, themes),
+    )
+    .map_err(|error| error.to_string())?;
+    match palette_issues(map, &palette).into_iter().next() {
+        Some(issue) => Err(issue.message),
+        None => Ok(palette),
+    }
+}
+
+/// It loads the palette of the map with the tiles the map brings and the theme applied
+///
+/// Arguments:
+///
+/// * `map_path`: The path of the map that will be spawned with the palette
+/// * `map`: The map, its own tiles are added to the palette
+/// * `theme`: The theme of the level
+///
+/// Returns:
+///
+/// The palette, or the error of the palette files
+pub fn load_palette(
+    map_path: &str,
+    map: &MapAsset,
+    theme: &Theme,
+) -> Result<MapPalette, PaletteError> {
+    Ok(load_level_palette(map_path)?
+        .with_overrides(&map.palette)
+        .themed(theme))
+}
+
+fn despawn_map(
+    mut commands: Commands,
+    map_objects: Query<Entity, With<MapObject>>,
+    mut grid_map: ResMut<GridMap>,
+    mut cleared_cells: ResMut<ClearedCells>,
+) {
+    for object in map_objects.iter() {
+        commands.entity(object).despawn_recursive();
+    }
+    *grid_map = GridMap::default();
+    *cleared_cells = ClearedCells::default();
 }
 
-fn despawn_map(mut commands: Commands, map_objects: Query<Entity, With<MapObject>>) {
+/// When the map file of the current level changes on disk, it despawns the map objects and spawns
+/// them again from the new content. The player keeps its position if it is still walkable, otherwise
+/// it goes back to the spawn point of the new map. The rules of the level are read again from the new
+/// map and the flags are removed, so they appear again on the new exit points. If the palette can't be
+/// loaded or the new map has characters missing from it, the current map and palette are kept
+///
+/// Arguments:
+///
+/// * `commands`: Commands
+/// * `map_events`: EventReader<AssetEvent<MapAsset>> - The events of the map assets
+/// * `asset_server`: Res<AssetServer>
+/// * `maps`: Res<Assets<MapAsset>> - The loaded maps
+/// * `current_map`: Res<CurrentMap> - The map of the level being played
+/// * `map_objects`: Query<Entity, With<MapObject>>
+/// * `bomb_query`: Query<&Transform, (With<Bomb>, Without<Player>)> - The bombs that are kept on the map
+/// * `player_query`: Query<&mut Transform, With<Player>>
+/// * `themes`: Res<ThemeSet>
+/// * `palette`: ResMut<MapPalette>
+/// * `grid_map`: ResMut<GridMap>
+/// * `flag_query`: Query<Entity, With<Flag>>
+/// * `rules`: ResMut<LevelRules>
+/// * `cleared_cells`: ResMut<ClearedCells> - The breakables of the new map are new, they can be hit again
+#[allow(clippy::too_many_arguments)]
+fn reload_map(
+    mut commands: Commands,
+    mut map_events: EventReader<AssetEvent<MapAsset>>,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
+    map_objects: Query<Entity, With<MapObject>>,
+    bomb_query: Query<&Transform, (With<Bomb>, Without<Player>)>,
+    mut player_query: Query<&mut Transform, With<Player>>,
+    themes: Res<ThemeSet>,
+    mut palette: ResMut<MapPalette>,
+    mut grid_map: ResMut<GridMap>,
+    flag_query: Query<Entity, With<Flag>>,
+    mut rules: ResMut<LevelRules>,
+    mut cleared_cells: ResMut<ClearedCells>,
+) {
+    let map_changed = map_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => *handle == current_map.handle,
+        _ => false,
+    });
+    if !map_changed {
+        return;
+    }
+    let map = match maps.get(&current_map.handle) {
+        Some(map) => map,
+        None => return,
+    };
+    info!("Reloading map {}", current_map.path);
+
+    //Keep the map being played if the new palette is broken or misses a character
+    let theme = current_map.theme(map, &themes);
+    *palette = match current_palette(&current_map, map, &themes) {
+        Ok(palette) => palette,
+        Err(error) => {
+            error!("Could not reload {}: {}", current_map.path, error);
+            return;
+        }
+    };
     for object in map_objects.iter() {
         commands.entity(object).despawn_recursive();
     }
+    *grid_map = spawn_map(&mut commands, &asset_server, map, &palette);
+    *cleared_cells = ClearedCells::default();
+    if let Some(decoration) = &current_map.decoration {
+        decorate_map(
+            &mut commands,
+            &asset_server,
+            map,
+            &grid_map,
+            decoration,
+            &palette,
+            &theme,
+        );
+    }
+
+    //Exit points, hidden items and header come from the new map, the countdown goes on if its limit
+    //is the same
+    let mut new_rules = LevelRules::new(map, &current_map);
+    if new_rules.meta.time_limit == rules.meta.time_limit {
+        new_rules.time_left = rules.time_left.take();
+    }
+    *rules = new_rules;
+    for flag in flag_query.iter() {
+        commands.entity(flag).despawn_recursive();
+    }
+
+    //Bombs are not part of the map, keep them on the new grid
+    for bomb_transform in bomb_query.iter() {
+        grid_map.set(
+            GridMap::world_to_cell(bomb_transform.translation),
+            CellContent::Bomb,
+        );
+    }
+
+    //Move the player back to its spawn if it is now inside a wall
+    for (player_index, mut player_transform) in player_query.iter_mut().enumerate() {
+        let player_cell = GridMap::world_to_cell(player_transform.translation);
+        let is_walkable =
+            matches!(grid_map.get(player_cell), Some(content) if content.is_walkable());
+        if !is_walkable {
+            player_transform.translation = map.spawn_position(player_index);
+        }
+    }
+}
+
+/// When F3 is pressed, it writes the current state of the level to a new map file under the export
+/// folder, so it can be attached to a bug report or opened in the editor. The map-local palette of
+/// imported maps is written next to it
+///
+/// Arguments:
+///
+/// * `keyboard`: Res<Input<KeyCode>>
+/// * `maps`: Res<Assets<MapAsset>>
+/// * `current_map`: Res<CurrentMap>
+/// * `palette`: Res<MapPalette>
+/// * `map_objects`: Query<(&Name, &Transform), With<MapObject>>
+fn export_current_map(
+    keyboard: Res<Input<KeyCode>>,
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
+    palette: Res<MapPalette>,
+    map_objects: Query<(&Name, &Transform), With<MapObject>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F3) {
+        return;
+    }
+    let map = match maps.get(&current_map.handle) {
+        Some(map) => map,
+        None => return,
+    };
+    let exported = export_map(
+        map,
+        &palette,
+        map_objects
+            .iter()
+            .map(|(name, transform)| (name.as_str(), transform.translation)),
+    );
+
+    let stem = Path::new(&current_map.path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("map"));
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let export_path = format!("{}/{}_{}.txt", MAP_EXPORT_FOLDER, stem, seconds);
+
+    //The tiles of imported maps go to the palette override next to the exported map
+    match fs::create_dir_all(MAP_EXPORT_FOLDER)
+        .map_err(|error| error.to_string())
+        .and_then(|_| exported.save(&export_path))
+    {
+        Ok(()) => info!("Exported the level to {}", export_path),
+        Err(error) => error!("Could not export the level to {}: {}", export_path, error),
+    }
 }
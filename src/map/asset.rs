@@ -0,0 +1,587 @@
+use std::{fmt, fs, io};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+
+use crate::constants::{FLAG_POSITION, PLAYER_SPAWN_POSITION};
+
+use super::{
+    grid::GridMap,
+    hidden::{hidden_layer_lines, read_hidden_layer, HiddenItem},
+    meta::{MapMeta, HEADER_END, MAP_FORMAT_VERSION},
+    palette::{palette_override_path, MapPalette},
+    tiled::import_tiled,
+};
+
+/// Cell where a player starts, a map can have one per player
+pub const SPAWN_CHAR: char = 'P';
+/// Cell where the flag appears once the level is cleared
+pub const EXIT_CHAR: char = 'E';
+/// What the spawn and exit cells become on the grid
+const MARKER_FLOOR_CHAR: char = ' ';
+/// Start of the line that closes the object grid or a layer and starts the layer named after it
+const LAYER_START: &str = "--- ";
+/// Floor tiles drawn under the objects
+pub const FLOOR_LAYER: &str = "floor";
+/// What the breakables leave behind when they are destroyed
+pub const HIDDEN_LAYER: &str = "hidden";
+/// Floor layer cell that keeps the floor the object layer gives
+pub const NO_FLOOR_CHAR: char = ' ';
+
+/// Tile grid of a map file, each row is a line of the file and each character a cell.
+/// Version 2 maps start with a header that fills `meta`.
+/// Spawn and exit characters are recorded apart and replaced by floor in `rows`.
+/// `palette` has the tiles the map brings on top of the level palette, like the ones of Tiled maps.
+/// `floor_rows` is the optional floor layer, it sets the floor under each cell of `rows`.
+/// `hidden` has what the breakables of the hidden layer leave behind, the others roll a random upgrade
+#[derive(TypeUuid, Clone, Debug, Default)]
+#[uuid = "8451c451-5d1e-4314-b5eb-a8e47bdc4b8b"]
+pub struct MapAsset {
+    pub meta: MapMeta,
+    pub rows: Vec<Vec<char>>,
+    pub floor_rows: Vec<Vec<char>>,
+    pub hidden: HashMap<IVec2, HiddenItem>,
+    pub spawn_points: Vec<IVec2>,
+    pub exit_points: Vec<IVec2>,
+    pub palette: MapPalette,
+}
+
+#[derive(Debug)]
+pub enum MapParseError {
+    InvalidUtf8,
+    Empty,
+    UnsupportedVersion(String),
+    InvalidHeader { line: usize, message: String },
+    InvalidLayer { line: usize, message: String },
+    MissingHeaderEnd,
+    Tiled(String),
+}
+
+impl fmt::Display for MapParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapParseError::InvalidUtf8 => write!(f, "map is not valid UTF-8"),
+            MapParseError::Empty => write!(f, "map has no tiles"),
+            MapParseError::UnsupportedVersion(version) => {
+                write!(f, "unsupported map version {}", version)
+            }
+            MapParseError::InvalidHeader { line, message } => {
+                write!(f, "invalid header at line {}: {}", line, message)
+            }
+            MapParseError::InvalidLayer { line, message } => {
+                write!(f, "invalid layer at line {}: {}", line, message)
+            }
+            MapParseError::MissingHeaderEnd => {
+                write!(f, "header is not closed by a {:?} line", HEADER_END)
+            }
+            MapParseError::Tiled(message) => write!(f, "invalid Tiled map: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for MapParseError {}
+
+impl MapAsset {
+    /// It parses the content of a map file. Files starting with a `version:` line have a header
+    /// closed by `---`, files without it are version 1 maps that only have the tile grid.
+    /// A `--- floor` or `--- hidden` line after the grid starts that layer
+    ///
+    /// Arguments:
+    ///
+    /// * `text`: The content of the map file
+    ///
+    /// Returns:
+    ///
+    /// The parsed map or the parse error
+    pub fn parse(text: &str) -> Result<MapAsset, MapParseError> {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut meta = MapMeta::default();
+        let mut grid_start = 0;
+
+        let has_header =
+            matches!(lines.first(), Some(line) if line.trim_start().starts_with("version:"));
+        if has_header {
+            let version = lines[0].trim_start()["version:".len()..].trim();
+            if version != MAP_FORMAT_VERSION.to_string() {
+                return Err(MapParseError::UnsupportedVersion(version.to_owned()));
+            }
+            let header_end = lines
+                .iter()
+                .position(|line| line.trim() == HEADER_END)
+                .ok_or(MapParseError::MissingHeaderEnd)?;
+            for (index, line) in lines.iter().enumerate().take(header_end).skip(1) {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let invalid_header = |message: String| MapParseError::InvalidHeader {
+                    line: index + 1,
+                    message,
+                };
+                let (key, value) = line
+                    .split_once(':')
+                    .ok_or_else(|| invalid_header(String::from("expected `key: value`")))?;
+                meta.set_field(key.trim(), value.trim())
+                    .map_err(invalid_header)?;
+            }
+            grid_start = header_end + 1;
+        }
+
+        //The object grid goes until the first layer, each layer until the next one
+        let mut layer_starts: Vec<usize> = (grid_start..lines.len())
+            .filter(|index| lines[*index].starts_with(LAYER_START))
+            .collect();
+        let grid_end = layer_starts.first().copied().unwrap_or(lines.len());
+        let read_rows = |lines: &[&str]| -> Vec<Vec<char>> {
+            lines.iter().map(|line| line.chars().collect()).collect()
+        };
+        let rows = read_rows(&lines[grid_start..grid_end]);
+        if rows.iter().all(|row| row.is_empty()) {
+            return Err(MapParseError::Empty);
+        }
+        let mut map = MapAsset::from_rows(meta, rows);
+
+        layer_starts.push(lines.len());
+        for layer in layer_starts.windows(2) {
+            let (start, end) = (layer[0], layer[1]);
+            let layer_lines = &lines[start + 1..end];
+            match lines[start][LAYER_START.len()..].trim() {
+                FLOOR_LAYER => map.floor_rows = read_rows(layer_lines),
+                HIDDEN_LAYER => {
+                    map.hidden = read_hidden_layer(layer_lines).map_err(|(row, message)| {
+                        MapParseError::InvalidLayer {
+                            line: start + row + 2,
+                            message,
+                        }
+                    })?;
+                }
+                name => {
+                    return Err(MapParseError::InvalidLayer {
+                        line: start + 1,
+                        message: format!("unknown layer {:?}", name),
+                    })
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// It builds a map from its tile grid, taking out the spawn and exit characters
+    ///
+    /// Arguments:
+    ///
+    /// * `meta`: The header of the map
+    /// * `rows`: The tile grid, with the spawn and exit characters
+    ///
+    /// Returns:
+    ///
+    /// MapAsset
+    pub fn from_rows(meta: MapMeta, mut rows: Vec<Vec<char>>) -> MapAsset {
+        let mut spawn_points = Vec::new();
+        let mut exit_points = Vec::new();
+        for (z, row) in rows.iter_mut().enumerate() {
+            for (x, char) in row.iter_mut().enumerate() {
+                let cell = IVec2::new(x as i32, z as i32);
+                match *char {
+                    SPAWN_CHAR => spawn_points.push(cell),
+                    EXIT_CHAR => exit_points.push(cell),
+                    _ => continue,
+                }
+                *char = MARKER_FLOOR_CHAR;
+            }
+        }
+        MapAsset {
+            meta,
+            rows,
+            floor_rows: Vec::new(),
+            hidden: HashMap::new(),
+            spawn_points,
+            exit_points,
+            palette: MapPalette::default(),
+        }
+    }
+
+    /// It parses the raw bytes of a map file
+    pub fn from_bytes(bytes: &[u8]) -> Result<MapAsset, MapParseError> {
+        let text = std::str::from_utf8(bytes).map_err(|_| MapParseError::InvalidUtf8)?;
+        MapAsset::parse(text)
+    }
+
+    /// It parses the raw bytes of a map file with the importer of its extension, `.tmj` files are
+    /// Tiled maps and any other file is a text map
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: The path of the map file
+    /// * `bytes`: The content of the map file
+    ///
+    /// Returns:
+    ///
+    /// The parsed map or the parse error
+    pub fn from_file_bytes(path: &str, bytes: &[u8]) -> Result<MapAsset, MapParseError> {
+        if path.ends_with(".tmj") {
+            import_tiled(bytes)
+        } else {
+            MapAsset::from_bytes(bytes)
+        }
+    }
+
+    /// The length of the longest row
+    pub fn width(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).max().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// It returns the character of the cell, or `None` if the row is shorter or the cell is outside the map
+    pub fn get(&self, cell: IVec2) -> Option<char> {
+        if cell.x < 0 || cell.y < 0 {
+            return None;
+        }
+        self.rows
+            .get(cell.y as usize)
+            .and_then(|row| row.get(cell.x as usize))
+            .copied()
+    }
+
+    /// It returns the character of the floor layer on the cell, or `None` if the map has no floor
+    /// layer there and the object layer gives the floor
+    pub fn floor_at(&self, cell: IVec2) -> Option<char> {
+        if cell.x < 0 || cell.y < 0 {
+            return None;
+        }
+        self.floor_rows
+            .get(cell.y as usize)
+            .and_then(|row| row.get(cell.x as usize))
+            .copied()
+            .filter(|char| *char != NO_FLOOR_CHAR)
+    }
+
+    /// It writes the map in the map file format, the spawn and exit characters are put back on the
+    /// grid. The header is only written when a field of it is set, and the floor layer when the map has one
+    pub fn to_text(&self) -> String {
+        let mut rows = self.rows.clone();
+        let markers = self
+            .spawn_points
+            .iter()
+            .map(|cell| (cell, SPAWN_CHAR))
+            .chain(self.exit_points.iter().map(|cell| (cell, EXIT_CHAR)));
+        for (cell, char) in markers {
+            if let Some(tile) = rows
+                .get_mut(cell.y as usize)
+                .and_then(|row| row.get_mut(cell.x as usize))
+            {
+                *tile = char;
+            }
+        }
+
+        let mut lines = Vec::new();
+        if self.meta != MapMeta::default() {
+            lines.push(format!("version: {}", MAP_FORMAT_VERSION));
+            lines.extend(self.meta.to_lines());
+            lines.push(HEADER_END.to_owned());
+        }
+        lines.extend(rows.iter().map(|row| row.iter().collect::<String>()));
+        if !self.floor_rows.is_empty() {
+            lines.push(format!("{}{}", LAYER_START, FLOOR_LAYER));
+            lines.extend(
+                self.floor_rows
+                    .iter()
+                    .map(|row| row.iter().collect::<String>()),
+            );
+        }
+        if !self.hidden.is_empty() {
+            lines.push(format!("{}{}", LAYER_START, HIDDEN_LAYER));
+            lines.extend(hidden_layer_lines(&self.hidden));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// It writes the map as a text map file. Maps of other formats, like Tiled ones, are written to a
+    /// `.txt` file next to the original instead of over it. The map-local palette can't be written in
+    /// the text format, so it goes to the palette override next to the text file
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: The path of the map file, relative to the working directory
+    ///
+    /// Returns:
+    ///
+    /// The path of the text map that was written or the error found while writing it
+    pub fn save(&self, path: &str) -> Result<String, String> {
+        let text_path = text_map_path(path);
+        if !self.palette.tiles.is_empty() || self.palette.default_floor.is_some() {
+            let palette = self.palette.to_ron().map_err(|error| error.to_string())?;
+            fs::write(palette_override_path(&text_path), palette)
+                .map_err(|error| error.to_string())?;
+        }
+        fs::write(&text_path, self.to_text()).map_err(|error| error.to_string())?;
+        Ok(text_path)
+    }
+
+    /// It returns the cell where the first player spawns, maps without spawn points use the default one
+    pub fn spawn_cell(&self) -> IVec2 {
+        self.spawn_points
+            .first()
+            .copied()
+            .unwrap_or_else(|| GridMap::world_to_cell(PLAYER_SPAWN_POSITION))
+    }
+
+    /// It returns where a player spawns, players beyond the amount of spawn points share them
+    ///
+    /// Arguments:
+    ///
+    /// * `player_index`: The index of the player, starting at 0
+    ///
+    /// Returns:
+    ///
+    /// Vec3
+    pub fn spawn_position(&self, player_index: usize) -> Vec3 {
+        if self.spawn_points.is_empty() {
+            return PLAYER_SPAWN_POSITION;
+        }
+        let cell = self.spawn_points[player_index % self.spawn_points.len()];
+        GridMap::cell_to_world(cell) + Vec3::Y * PLAYER_SPAWN_POSITION.y
+    }
+
+    /// It returns the exit points hidden under breakables, row by row
+    pub fn hidden_exits(&self) -> Vec<IVec2> {
+        let mut exits: Vec<IVec2> = self
+            .hidden
+            .iter()
+            .filter(|(_, item)| **item == HiddenItem::Exit)
+            .map(|(cell, _)| *cell)
+            .collect();
+        exits.sort_by_key(|cell| (cell.y, cell.x));
+        exits
+    }
+
+    /// It returns where the flags appear, exits hidden under breakables included. Maps without exit
+    /// points use the default one
+    pub fn exit_positions(&self) -> Vec<Vec3> {
+        let mut exits: Vec<IVec2> = self.exit_points.clone();
+        exits.extend(self.hidden_exits());
+        if exits.is_empty() {
+            return vec![FLAG_POSITION];
+        }
+        exits
+            .iter()
+            .map(|cell| GridMap::cell_to_world(*cell) + Vec3::Y * FLAG_POSITION.y)
+            .collect()
+    }
+
+    /// It returns every cell of the map with its character, row by row
+    pub fn cells(&self) -> impl Iterator<Item = (IVec2, char)> + '_ {
+        layer_cells(&self.rows)
+    }
+
+    /// It returns every cell of the floor layer with its character, the cells that keep the floor of
+    /// the object layer are skipped
+    pub fn floor_cells(&self) -> impl Iterator<Item = (IVec2, char)> + '_ {
+        layer_cells(&self.floor_rows).filter(|(_, char)| *char != NO_FLOOR_CHAR)
+    }
+}
+
+/// It returns every cell of a layer with its character, row by row
+fn layer_cells(rows: &[Vec<char>]) -> impl Iterator<Item = (IVec2, char)> + '_ {
+    rows.iter().enumerate().flat_map(|(z, row)| {
+        row.iter()
+            .enumerate()
+            .map(move |(x, char)| (IVec2::new(x as i32, z as i32), *char))
+    })
+}
+
+#[derive(Default)]
+pub struct MapAssetLoader;
+
+impl AssetLoader for MapAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let map = MapAsset::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["txt"]
+    }
+}
+
+/// It returns the path a map is saved to, `.tmj` maps are saved as `.txt` maps next to them
+///
+/// Arguments:
+///
+/// * `path`: The path of the map file
+///
+/// Returns:
+///
+/// String
+pub fn text_map_path(path: &str) -> String {
+    match path.strip_suffix(".tmj") {
+        Some(stem) => format!("{}.txt", stem),
+        None => path.to_owned(),
+    }
+}
+
+/// It lists every `.txt` and `.tmj` map inside the folder, sorted by name
+///
+/// Arguments:
+///
+/// * `folder`: The folder with the maps
+///
+/// Returns:
+///
+/// The paths of the maps, prefixed by the folder
+pub fn list_map_files(folder: &str) -> io::Result<Vec<String>> {
+    let mut map_paths: Vec<String> = fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == "txt" || extension == "tmj")
+        })
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    map_paths.sort();
+    Ok(map_paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        collider::UpgradeType,
+        map::meta::{BombRule, WinCondition},
+    };
+
+    const LAYERED_MAP: &str = "version: 2
+name: Frozen Lake
+theme: snow
+time_limit: 120
+bombs: 5
+win: survive
+bomb_rule: capacity
+---
+$$$$$$
+$P@&E$
+$%%%%$
+$$$$$$
+--- floor
+      
+  %%  
+--- hidden
+  C.
+";
+
+    #[test]
+    fn header_and_layers_are_parsed() {
+        let map = MapAsset::parse(LAYERED_MAP).unwrap();
+        assert_eq!(map.meta.name.as_deref(), Some("Frozen Lake"));
+        assert_eq!(map.meta.theme.as_deref(), Some("snow"));
+        assert_eq!(map.meta.time_limit, Some(120.0));
+        assert_eq!(map.meta.starting_bombs(), 5);
+        assert_eq!(map.meta.win_condition(), WinCondition::Survive);
+        assert_eq!(map.meta.bomb_rule, Some(BombRule::Capacity));
+
+        assert_eq!(map.rows.len(), 4);
+        assert_eq!(map.spawn_points, vec![IVec2::new(1, 1)]);
+        assert_eq!(map.exit_points, vec![IVec2::new(4, 1)]);
+        assert_eq!(map.get(IVec2::new(1, 1)), Some(' '));
+        assert_eq!(map.floor_at(IVec2::new(2, 1)), Some('%'));
+        assert_eq!(map.floor_at(IVec2::new(1, 1)), None);
+        assert_eq!(
+            map.hidden.get(&IVec2::new(2, 0)),
+            Some(&HiddenItem::Upgrade(UpgradeType::Bomb))
+        );
+        assert_eq!(
+            map.hidden.get(&IVec2::new(3, 0)),
+            Some(&HiddenItem::Nothing)
+        );
+    }
+
+    #[test]
+    fn to_text_round_trips() {
+        let map = MapAsset::parse(LAYERED_MAP).unwrap();
+        let reparsed = MapAsset::parse(&map.to_text()).unwrap();
+        assert_eq!(reparsed.meta, map.meta);
+        assert_eq!(reparsed.rows, map.rows);
+        assert_eq!(reparsed.floor_rows, map.floor_rows);
+        assert_eq!(reparsed.hidden, map.hidden);
+        assert_eq!(reparsed.spawn_points, map.spawn_points);
+        assert_eq!(reparsed.exit_points, map.exit_points);
+        assert_eq!(reparsed.to_text(), map.to_text());
+    }
+
+    #[test]
+    fn every_exit_gets_a_flag() {
+        let map = MapAsset::parse("$$$$$$\n$PE@E$\n$$$$$$\n--- hidden\n\n   E\n").unwrap();
+        assert_eq!(map.exit_points, vec![IVec2::new(2, 1), IVec2::new(4, 1)]);
+        assert_eq!(map.hidden_exits(), vec![IVec2::new(3, 1)]);
+        assert_eq!(map.exit_positions().len(), 3);
+    }
+
+    #[test]
+    fn headerless_maps_keep_working() {
+        let map = MapAsset::parse("$$$\n$P$\n$$$\n").unwrap();
+        assert_eq!(map.meta, MapMeta::default());
+        assert_eq!(map.spawn_cell(), IVec2::new(1, 1));
+        assert_eq!(map.to_text(), "$$$\n$P$\n$$$\n");
+    }
+
+    #[test]
+    fn tiled_maps_are_saved_next_to_the_original() {
+        let folder = std::env::temp_dir().join(format!("map_save_{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let tiled_path = folder.join("imported.tmj").to_string_lossy().into_owned();
+        fs::write(&tiled_path, "{}").unwrap();
+
+        let mut map = MapAsset::parse("$$$\n$P$\n$$$\n").unwrap();
+        map.palette = MapPalette::from_ron(
+            "(tiles: { '$': (name: \"Wall\", add_floor: false, path: \"objects/wall.glb#Scene0\", \
+             is_floor: false, upgrade: None, animated_rotation: false, breakable: false, custom: None) })",
+        )
+        .unwrap();
+        let saved_path = map.save(&tiled_path).unwrap();
+
+        assert_eq!(saved_path, text_map_path(&tiled_path));
+        assert_eq!(fs::read_to_string(&tiled_path).unwrap(), "{}");
+        assert_eq!(fs::read_to_string(&saved_path).unwrap(), map.to_text());
+        let palette = MapPalette::from_file(&palette_override_path(&saved_path)).unwrap();
+        assert_eq!(palette.get('$').unwrap().name, "Wall");
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn invalid_files_report_their_line() {
+        assert!(matches!(
+            MapAsset::parse("version: 1\n---\n$$$\n"),
+            Err(MapParseError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            MapAsset::parse("version: 2\nname: a\n$$$\n"),
+            Err(MapParseError::MissingHeaderEnd)
+        ));
+        assert!(matches!(
+            MapAsset::parse("version: 2\nname: a\nbombs: many\n---\n$$$\n"),
+            Err(MapParseError::InvalidHeader { line: 3, .. })
+        ));
+        assert!(matches!(
+            MapAsset::parse("$$$\n--- walls\n$$$\n"),
+            Err(MapParseError::InvalidLayer { line: 2, .. })
+        ));
+        assert!(matches!(
+            MapAsset::parse("$$$\n--- hidden\n\n Z\n"),
+            Err(MapParseError::InvalidLayer { line: 4, .. })
+        ));
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::DEFAULT_OBJECT_SCALE;
+
+use super::{asset::MapAsset, palette::MapPalette, CustomProps, ObjectProps};
+
+/// Neighbours in the order of the connection bits: north, east, south and west
+const DIRECTIONS: [IVec2; 4] = [
+    IVec2::new(0, -1),
+    IVec2::new(1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(-1, 0),
+];
+
+const NORTH: u8 = 1;
+const EAST: u8 = 2;
+const SOUTH: u8 = 4;
+const WEST: u8 = 8;
+
+/// Connections of each piece as it is modelled, before any rotation
+const END_CONNECTIONS: u8 = SOUTH;
+const STRAIGHT_CONNECTIONS: u8 = NORTH | SOUTH;
+const CORNER_CONNECTIONS: u8 = EAST | SOUTH;
+const SPLIT_CONNECTIONS: u8 = EAST | SOUTH | WEST;
+
+/// Models used to draw a path, the piece of each cell depends on which neighbours are the same path
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AutotileSet {
+    /// A path cell without neighbours
+    pub single: String,
+    pub end: String,
+    pub straight: String,
+    pub corner: String,
+    /// Three connections
+    pub split: String,
+    pub crossing: String,
+}
+
+impl AutotileSet {
+    /// It picks the piece and the rotation that match the connections of a cell
+    ///
+    /// Arguments:
+    ///
+    /// * `connections`: The neighbours that are the same path, as NORTH | EAST | SOUTH | WEST bits
+    ///
+    /// Returns:
+    ///
+    /// The path of the model and its rotation around the Y axis
+    pub fn piece(&self, connections: u8) -> (&str, Quat) {
+        let (path, modelled) = match connections.count_ones() {
+            0 => return (&self.single, Quat::IDENTITY),
+            1 => (&self.end, END_CONNECTIONS),
+            2 if connections == NORTH | SOUTH || connections == EAST | WEST => {
+                (&self.straight, STRAIGHT_CONNECTIONS)
+            }
+            2 => (&self.corner, CORNER_CONNECTIONS),
+            3 => (&self.split, SPLIT_CONNECTIONS),
+            _ => return (&self.crossing, Quat::IDENTITY),
+        };
+        //Quarter turns clockwise, seen from above, until the model matches the connections
+        let turns = (0..4)
+            .find(|turns| rotate_connections(modelled, *turns) == connections)
+            .unwrap_or(0);
+        (
+            path,
+            Quat::from_rotation_y(-(turns as f32) * std::f32::consts::FRAC_PI_2),
+        )
+    }
+
+    /// It returns a copy of the set with every path changed by `resolve`
+    pub fn map_paths(&self, resolve: impl Fn(&str) -> String) -> AutotileSet {
+        AutotileSet {
+            single: resolve(&self.single),
+            end: resolve(&self.end),
+            straight: resolve(&self.straight),
+            corner: resolve(&self.corner),
+            split: resolve(&self.split),
+            crossing: resolve(&self.crossing),
+        }
+    }
+}
+
+/// It turns the connections clockwise by quarter turns, north becomes east
+fn rotate_connections(connections: u8, turns: u32) -> u8 {
+    let turns = turns % 4;
+    ((connections << turns) | (connections >> (4 - turns))) & 0b1111
+}
+
+/// It returns which neighbours of the cell have the same character. The floor layer is checked
+/// first, so a path connects with the path drawn under the objects next to it
+///
+/// Arguments:
+///
+/// * `map`: The parsed map
+/// * `cell`: The cell to check
+/// * `char`: The character of the path
+///
+/// Returns:
+///
+/// The connections as NORTH | EAST | SOUTH | WEST bits
+pub fn connections(map: &MapAsset, cell: IVec2, char: char) -> u8 {
+    DIRECTIONS
+        .iter()
+        .enumerate()
+        .filter(|(_, direction)| {
+            let neighbour = cell + **direction;
+            map.floor_at(neighbour).or_else(|| map.get(neighbour)) == Some(char)
+        })
+        .fold(0, |connections, (bit, _)| connections | 1 << bit)
+}
+
+/// It chooses the connected piece of every autotiled cell of the map
+///
+/// Arguments:
+///
+/// * `map`: The parsed map
+/// * `palette`: The palette the map is spawned with
+///
+/// Returns:
+///
+/// The props to spawn on each autotiled cell, the other cells use the palette as is
+pub fn autotile(map: &MapAsset, palette: &MapPalette) -> HashMap<IVec2, ObjectProps> {
+    autotile_cells(map, palette, map.cells())
+}
+
+/// It chooses the connected piece of every autotiled cell of the floor layer
+///
+/// Arguments:
+///
+/// * `map`: The parsed map
+/// * `palette`: The palette the map is spawned with
+///
+/// Returns:
+///
+/// The props of the floor to spawn on each autotiled cell of the floor layer
+pub fn autotile_floor(map: &MapAsset, palette: &MapPalette) -> HashMap<IVec2, ObjectProps> {
+    autotile_cells(map, palette, map.floor_cells())
+}
+
+fn autotile_cells(
+    map: &MapAsset,
+    palette: &MapPalette,
+    cells: impl Iterator<Item = (IVec2, char)>,
+) -> HashMap<IVec2, ObjectProps> {
+    let mut tiles = HashMap::new();
+    for (cell, char) in cells {
+        let props = match palette.get(char) {
+            Some(props) => props,
+            None => continue,
+        };
+        let set = match &props.autotile {
+            Some(set) => set,
+            None => continue,
+        };
+        let (path, rotation) = set.piece(connections(map, cell, char));
+
+        let mut tile = props.clone();
+        tile.path = path.to_owned();
+        let mut custom = tile.custom.unwrap_or(CustomProps {
+            scale: DEFAULT_OBJECT_SCALE.to_owned(),
+            rotation: Quat::IDENTITY,
+            sum_translation: Vec3::ZERO,
+        });
+        custom.rotation = rotation * custom.rotation;
+        tile.custom = Some(custom);
+        tiles.insert(cell, tile);
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+    use crate::{constants::MAP_PALETTE_PATH, map::meta::MapMeta};
+
+    fn set() -> AutotileSet {
+        AutotileSet {
+            single: String::from("single"),
+            end: String::from("end"),
+            straight: String::from("straight"),
+            corner: String::from("corner"),
+            split: String::from("split"),
+            crossing: String::from("crossing"),
+        }
+    }
+
+    fn assert_piece(connections: u8, path: &str, angle: f32) {
+        let set = set();
+        let (piece, rotation) = set.piece(connections);
+        assert_eq!(piece, path, "connections {:04b}", connections);
+        assert!(
+            rotation.abs_diff_eq(Quat::from_rotation_y(angle), 1e-5)
+                || rotation.abs_diff_eq(-Quat::from_rotation_y(angle), 1e-5),
+            "connections {:04b} rotated {:?}",
+            connections,
+            rotation
+        );
+    }
+
+    #[test]
+    fn piece_and_rotation_follow_the_connections() {
+        assert_piece(0, "single", 0.0);
+        assert_piece(SOUTH, "end", 0.0);
+        assert_piece(WEST, "end", -FRAC_PI_2);
+        assert_piece(NORTH, "end", -PI);
+        assert_piece(NORTH | SOUTH, "straight", 0.0);
+        assert_piece(EAST | WEST, "straight", -FRAC_PI_2);
+        assert_piece(EAST | SOUTH, "corner", 0.0);
+        assert_piece(SOUTH | WEST, "corner", -FRAC_PI_2);
+        assert_piece(NORTH | EAST, "corner", FRAC_PI_2);
+        assert_piece(EAST | SOUTH | WEST, "split", 0.0);
+        assert_piece(NORTH | EAST | SOUTH, "split", FRAC_PI_2);
+        assert_piece(NORTH | EAST | SOUTH | WEST, "crossing", 0.0);
+    }
+
+    #[test]
+    fn paths_connect_to_their_neighbours() {
+        let rows = vec![vec!['%', '%', ' '], vec![' ', '%', ' ']];
+        let map = MapAsset::from_rows(MapMeta::default(), rows);
+        assert_eq!(connections(&map, IVec2::new(0, 0), '%'), EAST);
+        assert_eq!(connections(&map, IVec2::new(1, 0), '%'), SOUTH | WEST);
+        assert_eq!(connections(&map, IVec2::new(1, 1), '%'), NORTH);
+
+        let palette = MapPalette::from_file(MAP_PALETTE_PATH).unwrap();
+        let tiles = autotile(&map, &palette);
+        assert_eq!(tiles.len(), 3);
+        assert_eq!(tiles[&IVec2::new(0, 0)].path, "objects/tile_end.glb#Scene0");
+        assert_eq!(
+            tiles[&IVec2::new(1, 0)].path,
+            "objects/tile_cornerSquare.glb#Scene0"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::grid::{CellContent, GridMap};
+
+/// Settings of the decorations of a level, the same seed always gives the same decorations
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DecorationConfig {
+    pub seed: u64,
+    /// Chance of each out of play cell to get a detail, between 0 and 1
+    #[serde(default = "default_density")]
+    pub density: f32,
+    /// Cells around the map that are decorated
+    #[serde(default = "default_margin")]
+    pub margin: i32,
+    /// Models picked for the details, resolved through the theme of the level
+    #[serde(default = "default_details")]
+    pub details: Vec<String>,
+}
+
+fn default_density() -> f32 {
+    0.3
+}
+
+fn default_margin() -> i32 {
+    2
+}
+
+fn default_details() -> Vec<String> {
+    [
+        "objects/detail_tree.glb#Scene0",
+        "objects/detail_treeLarge.glb#Scene0",
+        "objects/detail_rocks.glb#Scene0",
+        "objects/detail_rocksLarge.glb#Scene0",
+        "objects/detail_crystal.glb#Scene0",
+        "objects/detail_crystalLarge.glb#Scene0",
+    ]
+    .iter()
+    .map(|path| path.to_string())
+    .collect()
+}
+
+/// A decorative detail placed on a cell, `ground` is set for the cells outside the map that need a floor
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decoration {
+    pub cell: IVec2,
+    pub detail: Option<String>,
+    pub rotation: f32,
+    pub ground: bool,
+}
+
+/// It places details on the cells the player never walks on: the margin around the map and the floor
+/// that can not be reached from the spawn
+///
+/// Arguments:
+///
+/// * `config`: The settings of the decorations
+/// * `grid_map`: The logical map of the level
+/// * `spawn`: The cell where the player spawns
+///
+/// Returns:
+///
+/// The decorations, in the same order for the same seed and map
+pub fn scatter(config: &DecorationConfig, grid_map: &GridMap, spawn: IVec2) -> Vec<Decoration> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let width = grid_map.width() as i32;
+    let height = grid_map.height() as i32;
+    let margin = config.margin.max(0);
+
+    let reached = grid_map.flood_fill(spawn, |content| {
+        content != CellContent::Wall && content != CellContent::Empty
+    });
+
+    let mut decorations = Vec::new();
+    for z in -margin..height + margin {
+        for x in -margin..width + margin {
+            let cell = IVec2::new(x, z);
+            let ground = !grid_map.in_bounds(cell);
+            let out_of_play = ground
+                || (grid_map.get(cell) == Some(CellContent::Floor) && !reached.contains(&cell));
+            if !out_of_play {
+                continue;
+            }
+
+            //Both rolls are made on every cell so a detail does not shift the ones after it
+            let roll = rng.gen::<f32>();
+            let detail = config.details.choose(&mut rng).cloned();
+            let rotation = rng.gen_range(0.0..std::f32::consts::TAU);
+            let detail = if roll < config.density { detail } else { None };
+            if detail.is_none() && !ground {
+                continue;
+            }
+            decorations.push(Decoration {
+                cell,
+                detail,
+                rotation,
+                ground,
+            });
+        }
+    }
+    decorations
+}
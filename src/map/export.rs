@@ -0,0 +1,176 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::collider::UpgradeType;
+
+use super::{
+    asset::{MapAsset, NO_FLOOR_CHAR},
+    grid::GridMap,
+    hidden::HiddenItem,
+    palette::MapPalette,
+};
+
+/// Prefix of the names given by `spawn_floor`, the rest of the name is the tile it was spawned for
+const FLOOR_NAME_PREFIX: &str = "Floor#";
+const FLOOR_CHAR: char = ' ';
+
+/// It rebuilds the grid of a map from the objects that are alive, so destroyed breakables become floor
+/// and dropped items are kept. Floor tiles under objects are written to the floor layer
+///
+/// Arguments:
+///
+/// * `base`: The map the level was spawned from, its size, header, spawn and exit points are kept
+/// * `palette`: The palette the map was spawned with, objects are matched by name
+/// * `objects`: The name and position of every map object
+///
+/// Returns:
+///
+/// The map with the current state of the level
+pub fn export_map<'a>(
+    base: &MapAsset,
+    palette: &MapPalette,
+    objects: impl Iterator<Item = (&'a str, Vec3)>,
+) -> MapAsset {
+    //Several characters can share a name, the lowest one is used so exports are stable
+    let mut chars_by_name: HashMap<&str, char> = HashMap::new();
+    for (key, props) in palette.tiles.iter() {
+        let char = chars_by_name.entry(props.name.as_str()).or_insert(*key);
+        *char = (*char).min(*key);
+    }
+
+    let mut floors = HashMap::new();
+    let mut tiles = HashMap::new();
+    for (name, translation) in objects {
+        let cell = GridMap::world_to_cell(translation);
+        match name.strip_prefix(FLOOR_NAME_PREFIX) {
+            //The floor under an object is the default floor, floor tiles keep their character
+            Some(tile_name) => {
+                let char = chars_by_name
+                    .get(tile_name)
+                    .filter(|char| matches!(palette.get(**char), Some(props) if props.is_floor))
+                    .copied()
+                    .unwrap_or(FLOOR_CHAR);
+                floors.insert(cell, char);
+            }
+            None => {
+                if let Some(char) = chars_by_name.get(name) {
+                    tiles.insert(cell, *char);
+                }
+            }
+        }
+    }
+
+    let rows = base
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(z, row)| {
+            (0..row.len())
+                .map(|x| {
+                    let cell = IVec2::new(x as i32, z as i32);
+                    tiles
+                        .get(&cell)
+                        .or_else(|| floors.get(&cell))
+                        .copied()
+                        .unwrap_or(FLOOR_CHAR)
+                })
+                .collect()
+        })
+        .collect();
+
+    //Only the floor under an object needs the floor layer, the other floors are on the grid
+    let mut floor_rows: Vec<Vec<char>> = base
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(z, row)| {
+            (0..row.len())
+                .map(|x| {
+                    let cell = IVec2::new(x as i32, z as i32);
+                    match (tiles.get(&cell), floors.get(&cell)) {
+                        (Some(_), Some(floor)) => *floor,
+                        _ => NO_FLOOR_CHAR,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    if floor_rows
+        .iter()
+        .flatten()
+        .all(|char| *char == NO_FLOOR_CHAR)
+    {
+        floor_rows.clear();
+    }
+
+    //Breakables that are still standing keep what they hide, revealed exits become exit points
+    let mut hidden = HashMap::new();
+    let mut exit_points = base.exit_points.clone();
+    for (cell, item) in base.hidden.iter() {
+        let is_standing = matches!(
+            tiles.get(cell).and_then(|char| palette.get(*char)),
+            Some(props) if props.breakable && props.upgrade == UpgradeType::None
+        );
+        if is_standing {
+            hidden.insert(*cell, *item);
+        } else if *item == HiddenItem::Exit {
+            exit_points.push(*cell);
+        }
+    }
+
+    MapAsset {
+        meta: base.meta.clone(),
+        rows,
+        floor_rows,
+        hidden,
+        spawn_points: base.spawn_points.clone(),
+        exit_points,
+        palette: base.palette.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MAP_PALETTE_PATH;
+
+    #[test]
+    fn destroyed_breakables_become_floor() {
+        let palette = MapPalette::from_file(MAP_PALETTE_PATH).unwrap();
+        let base = MapAsset::parse("$$$$$\n$P@@$\n$$$$$\n--- hidden\n\n  EC\n").unwrap();
+        let name = |char: char| palette.get(char).unwrap().name.clone();
+
+        //The breakable at (2, 1) was destroyed, the one at (3, 1) still stands
+        let mut objects = Vec::new();
+        for (cell, char) in base.cells() {
+            let object = match char {
+                '@' if cell == IVec2::new(2, 1) => None,
+                '@' => Some(name('@')),
+                '$' => Some(name('$')),
+                _ => None,
+            };
+            let floor = match object {
+                Some(_) if char == '$' => None,
+                Some(_) => Some(format!("{}{}", FLOOR_NAME_PREFIX, name('@'))),
+                None => Some(format!("{}{}", FLOOR_NAME_PREFIX, name(' '))),
+            };
+            objects.extend(object.into_iter().chain(floor).map(|name| (name, cell)));
+        }
+        let exported = export_map(
+            &base,
+            &palette,
+            objects
+                .iter()
+                .map(|(name, cell)| (name.as_str(), GridMap::cell_to_world(*cell))),
+        );
+
+        //The exit hidden under the destroyed breakable is now an exit point
+        assert_eq!(exported.to_text().lines().nth(1), Some("$PE@$"));
+        assert_eq!(exported.exit_points, vec![IVec2::new(2, 1)]);
+        assert!(exported.floor_rows.is_empty());
+        assert_eq!(
+            exported.hidden.get(&IVec2::new(3, 1)),
+            Some(&HiddenItem::Upgrade(UpgradeType::Bomb))
+        );
+        assert_eq!(exported.hidden.len(), 1);
+    }
+}
@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    asset::{MapAsset, SPAWN_CHAR},
+    meta::MapMeta,
+};
+
+const BORDER_CHAR: char = '$';
+const CORNER_CHAR: char = 'R';
+const PILLAR_CHAR: char = 'B';
+const BREAKABLE_CHAR: char = '@';
+const FLOOR_CHAR: char = ' ';
+const BOMB_UP_CHAR: char = 'C';
+const FIRE_UP_CHAR: char = 'F';
+const SPEED_UP_CHAR: char = 'S';
+
+/// Settings of a procedurally generated map, the same seed always gives the same map
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GeneratorConfig {
+    pub seed: u64,
+    #[serde(default = "default_width")]
+    pub width: usize,
+    #[serde(default = "default_height")]
+    pub height: usize,
+    /// Chance of each free cell to get a breakable, between 0 and 1
+    #[serde(default = "default_density")]
+    pub density: f32,
+    /// Cells around the spawn, in steps, that are kept free
+    #[serde(default = "default_start_clearance")]
+    pub start_clearance: i32,
+    #[serde(default)]
+    pub bomb_ups: usize,
+    #[serde(default)]
+    pub fire_ups: usize,
+    #[serde(default)]
+    pub speed_ups: usize,
+}
+
+fn default_width() -> usize {
+    27
+}
+
+fn default_height() -> usize {
+    15
+}
+
+fn default_density() -> f32 {
+    0.4
+}
+
+fn default_start_clearance() -> i32 {
+    2
+}
+
+/// It generates a classic layout: border walls, a grid of pillars, breakables scattered over the free
+/// cells and items on the floor, leaving the area around the spawn free
+///
+/// Arguments:
+///
+/// * `config`: The settings of the map
+/// * `spawn`: The cell where the player spawns
+///
+/// Returns:
+///
+/// The generated map, with the same characters used by the map files and a spawn point at `spawn`, or
+/// an error if there are more items than free cells to place them
+pub fn generate(config: &GeneratorConfig, spawn: IVec2) -> Result<MapAsset, String> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let width = config.width.max(3) as i32;
+    let height = config.height.max(3) as i32;
+    let spawn = spawn.clamp(IVec2::ONE, IVec2::new(width - 2, height - 2));
+
+    let mut rows = vec![vec![FLOOR_CHAR; width as usize]; height as usize];
+    let mut free_cells = Vec::new();
+    for z in 0..height {
+        for x in 0..width {
+            let is_border_x = x == 0 || x == width - 1;
+            let is_border_z = z == 0 || z == height - 1;
+            let cell = IVec2::new(x, z);
+            let distance = (cell - spawn).abs();
+            let steps = distance.x + distance.y;
+            let char = if is_border_x && is_border_z {
+                CORNER_CHAR
+            } else if is_border_x || is_border_z {
+                BORDER_CHAR
+            } else if x % 2 == 0 && z % 2 == 0 && steps > 1 {
+                //The pillar grid starts at the border, the spawn and its neighbours are carved out
+                //of it so the player can always walk away
+                PILLAR_CHAR
+            } else {
+                if steps > config.start_clearance.max(1) {
+                    free_cells.push(cell);
+                }
+                FLOOR_CHAR
+            };
+            rows[z as usize][x as usize] = char;
+        }
+    }
+
+    let item_count = config.bomb_ups + config.fire_ups + config.speed_ups;
+    if item_count > free_cells.len() {
+        return Err(format!(
+            "{} items do not fit in the {} free cells of the generated map",
+            item_count,
+            free_cells.len()
+        ));
+    }
+
+    //Items are placed first so breakables never cover them
+    free_cells.shuffle(&mut rng);
+    let items = std::iter::repeat(BOMB_UP_CHAR)
+        .take(config.bomb_ups)
+        .chain(std::iter::repeat(FIRE_UP_CHAR).take(config.fire_ups))
+        .chain(std::iter::repeat(SPEED_UP_CHAR).take(config.speed_ups));
+    let mut remaining_cells = free_cells.into_iter();
+    for (item, cell) in items.zip(remaining_cells.by_ref()) {
+        rows[cell.y as usize][cell.x as usize] = item;
+    }
+    for cell in remaining_cells {
+        if rng.gen::<f32>() < config.density {
+            rows[cell.y as usize][cell.x as usize] = BREAKABLE_CHAR;
+        }
+    }
+
+    rows[spawn.y as usize][spawn.x as usize] = SPAWN_CHAR;
+    Ok(MapAsset::from_rows(MapMeta::default(), rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::MAP_PALETTE_PATH,
+        map::{
+            grid::{CellContent, GridMap},
+            palette::MapPalette,
+        },
+    };
+
+    fn config(seed: u64) -> GeneratorConfig {
+        GeneratorConfig {
+            seed,
+            width: default_width(),
+            height: default_height(),
+            density: default_density(),
+            start_clearance: default_start_clearance(),
+            bomb_ups: 2,
+            fire_ups: 1,
+            speed_ups: 1,
+        }
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        let spawn = IVec2::new(1, 1);
+        let first = generate(&config(42), spawn).unwrap();
+        let second = generate(&config(42), spawn).unwrap();
+        assert_eq!(first.rows, second.rows);
+        assert_ne!(first.rows, generate(&config(43), spawn).unwrap().rows);
+    }
+
+    #[test]
+    fn spawn_is_free_and_reaches_the_map() {
+        let palette = MapPalette::from_file(MAP_PALETTE_PATH).unwrap();
+        let spawns = [IVec2::new(5, 7), IVec2::new(4, 7), IVec2::new(4, 6)];
+        for (seed, spawn) in (0..20).zip(spawns.into_iter().cycle()) {
+            let map = generate(&config(seed), spawn).unwrap();
+            assert_eq!(map.spawn_cell(), spawn);
+
+            let grid_map = GridMap::from_map(&map, &palette);
+            //The player can walk away from the spawn before the first bomb
+            let walkable = grid_map.flood_fill(spawn, |content| content.is_walkable());
+            assert!(walkable.len() > 2, "seed {} traps the spawn", seed);
+            //Once the breakables are destroyed, every cell that is not a wall can be reached
+            let reachable = grid_map.flood_fill(spawn, |content| content != CellContent::Wall);
+            let open_cells = grid_map
+                .iter()
+                .filter(|(_, content)| *content != CellContent::Wall)
+                .count();
+            assert_eq!(reachable.len(), open_cells, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn pillars_are_anchored_to_the_border() {
+        let mut config = config(3);
+        config.density = 0.0;
+        for spawn in [IVec2::new(1, 1), IVec2::new(2, 1), IVec2::new(4, 6)] {
+            let map = generate(&config, spawn).unwrap();
+            for z in 1..default_height() as i32 - 1 {
+                for x in 1..default_width() as i32 - 1 {
+                    let cell = IVec2::new(x, z);
+                    let distance = (cell - spawn).abs();
+                    let is_pillar = x % 2 == 0 && z % 2 == 0 && distance.x + distance.y > 1;
+                    assert_eq!(
+                        map.get(cell) == Some(PILLAR_CHAR),
+                        is_pillar,
+                        "spawn {} cell {}",
+                        spawn,
+                        cell
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn too_many_items_is_an_error() {
+        let mut config = config(1);
+        config.width = 5;
+        config.height = 5;
+        config.bomb_ups = 50;
+        assert!(generate(&config, IVec2::ONE).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+    collider::UpgradeType,
+    constants::{MAP_CELL_SIZE, MAP_ORIGIN},
+};
+
+use super::{asset::MapAsset, palette::MapPalette, ObjectProps};
+
+/// Offsets of the four neighbours of a cell
+pub const NEIGHBOURS: [IVec2; 4] = [
+    IVec2::new(1, 0),
+    IVec2::new(-1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+];
+
+/// What is standing on a map cell
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellContent {
+    Empty,
+    Floor,
+    Wall,
+    Breakable,
+    Item(UpgradeType),
+    Bomb,
+}
+
+impl CellContent {
+    /// It returns the content a cell has when the object described by `object_props` is spawned on it
+    ///
+    /// Arguments:
+    ///
+    /// * `object_props`: The props of the spawned object
+    ///
+    /// Returns:
+    ///
+    /// CellContent
+    pub fn from_props(object_props: &ObjectProps) -> CellContent {
+        if object_props.upgrade != UpgradeType::None {
+            CellContent::Item(object_props.upgrade)
+        } else if object_props.breakable {
+            CellContent::Breakable
+        } else if object_props.is_floor {
+            CellContent::Floor
+        } else {
+            CellContent::Wall
+        }
+    }
+
+    /// Whether the player can walk over the cell
+    pub fn is_walkable(&self) -> bool {
+        matches!(
+            self,
+            CellContent::Floor | CellContent::Item(_) | CellContent::Bomb
+        )
+    }
+}
+
+/// Logical representation of the map, `x` is the column and `y` the row of a cell
+#[derive(Resource, Clone, Debug, Default)]
+pub struct GridMap {
+    width: usize,
+    height: usize,
+    cells: Vec<CellContent>,
+}
+
+impl GridMap {
+    /// It creates a grid where every cell is empty
+    ///
+    /// Arguments:
+    ///
+    /// * `width`: The amount of columns
+    /// * `height`: The amount of rows
+    ///
+    /// Returns:
+    ///
+    /// GridMap
+    pub fn new(width: usize, height: usize) -> GridMap {
+        GridMap {
+            width,
+            height,
+            cells: vec![CellContent::Empty; width * height],
+        }
+    }
+
+    /// It creates the grid of a map, characters missing from the palette are left empty
+    ///
+    /// Arguments:
+    ///
+    /// * `map`: The parsed map
+    /// * `palette`: The palette that relates each character with an object
+    ///
+    /// Returns:
+    ///
+    /// GridMap
+    pub fn from_map(map: &MapAsset, palette: &MapPalette) -> GridMap {
+        let mut grid_map = GridMap::new(map.width(), map.height());
+        for (cell, char) in map.cells() {
+            if let Some(object_props) = palette.get(char) {
+                grid_map.set(cell, CellContent::from_props(object_props));
+            }
+        }
+        grid_map
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn in_bounds(&self, cell: IVec2) -> bool {
+        cell.x >= 0
+            && cell.y >= 0
+            && (cell.x as usize) < self.width
+            && (cell.y as usize) < self.height
+    }
+
+    /// It returns the content of the cell, or `None` if the cell is outside the map
+    pub fn get(&self, cell: IVec2) -> Option<CellContent> {
+        if !self.in_bounds(cell) {
+            return None;
+        }
+        Some(self.cells[cell.y as usize * self.width + cell.x as usize])
+    }
+
+    /// It replaces the content of the cell, cells outside the map are ignored
+    pub fn set(&mut self, cell: IVec2, content: CellContent) {
+        if self.in_bounds(cell) {
+            self.cells[cell.y as usize * self.width + cell.x as usize] = content;
+        }
+    }
+
+    /// It returns every cell of the map with its content, row by row
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, CellContent)> + '_ {
+        self.cells.iter().enumerate().map(|(index, content)| {
+            (
+                IVec2::new((index % self.width) as i32, (index / self.width) as i32),
+                *content,
+            )
+        })
+    }
+
+    /// It counts how many cells match the `content`
+    pub fn count(&self, content: CellContent) -> usize {
+        self.cells.iter().filter(|cell| **cell == content).count()
+    }
+
+    /// It returns every cell that can be reached from `start` moving through the `passable` cells
+    ///
+    /// Arguments:
+    ///
+    /// * `start`: The first cell, it is always part of the result if it is inside the map
+    /// * `passable`: Whether a cell content can be crossed
+    ///
+    /// Returns:
+    ///
+    /// HashSet<IVec2>
+    pub fn flood_fill(
+        &self,
+        start: IVec2,
+        passable: impl Fn(CellContent) -> bool,
+    ) -> HashSet<IVec2> {
+        let mut reached = HashSet::default();
+        if !self.in_bounds(start) {
+            return reached;
+        }
+        let mut pending = vec![start];
+        reached.insert(start);
+        while let Some(cell) = pending.pop() {
+            for offset in NEIGHBOURS {
+                let neighbour = cell + offset;
+                match self.get(neighbour) {
+                    Some(content) if passable(content) && !reached.contains(&neighbour) => {
+                        reached.insert(neighbour);
+                        pending.push(neighbour);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        reached
+    }
+
+    /// It returns the cells reached by the explosion of a bomb placed at `origin`. The explosion
+    /// spreads in a cross up to `range` cells, walls stop it and it stops after the first breakable,
+    /// item or bomb, which are reached
+    ///
+    /// Arguments:
+    ///
+    /// * `origin`: The cell of the bomb
+    /// * `range`: The amount of cells reached in each direction
+    ///
+    /// Returns:
+    ///
+    /// Vec<IVec2>
+    pub fn blast_cells(&self, origin: IVec2, range: i32) -> Vec<IVec2> {
+        let mut cells = vec![origin];
+        for direction in NEIGHBOURS {
+            for distance in 1..=range {
+                let cell = origin + direction * distance;
+                match self.get(cell) {
+                    Some(CellContent::Floor) => cells.push(cell),
+                    Some(CellContent::Breakable | CellContent::Item(_) | CellContent::Bomb) => {
+                        cells.push(cell);
+                        break;
+                    }
+                    Some(CellContent::Wall | CellContent::Empty) | None => break,
+                }
+            }
+        }
+        cells
+    }
+
+    /// It converts a bomb range in world units into the amount of cells it reaches
+    pub fn range_to_cells(range: f32) -> i32 {
+        (range / MAP_CELL_SIZE).round() as i32
+    }
+
+    /// It converts a world position into the map cell that contains it
+    ///
+    /// Arguments:
+    ///
+    /// * `translation`: The world position
+    ///
+    /// Returns:
+    ///
+    /// IVec2
+    pub fn world_to_cell(translation: Vec3) -> IVec2 {
+        let local = (translation - MAP_ORIGIN) / MAP_CELL_SIZE;
+        IVec2::new(local.x.round() as i32, local.z.round() as i32)
+    }
+
+    /// It converts a map cell into the world position of its center, at the floor height
+    ///
+    /// Arguments:
+    ///
+    /// * `cell`: The map cell
+    ///
+    /// Returns:
+    ///
+    /// Vec3
+    pub fn cell_to_world(cell: IVec2) -> Vec3 {
+        MAP_ORIGIN + Vec3::new(cell.x as f32, 0.0, cell.y as f32) * MAP_CELL_SIZE
+    }
+}
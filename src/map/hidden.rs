@@ -0,0 +1,120 @@
+use std::fmt;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::collider::UpgradeType;
+
+/// Hidden layer cell of a breakable that keeps the random drop
+pub const RANDOM_DROP_CHAR: char = ' ';
+
+/// What a breakable leaves behind when it is destroyed, breakables without one roll a random upgrade
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HiddenItem {
+    Upgrade(UpgradeType),
+    Nothing,
+    /// The exit point is under the breakable, the flag appears there once the level is cleared
+    Exit,
+}
+
+impl HiddenItem {
+    /// It reads a hidden item from its character in the hidden layer
+    pub fn from_char(char: char) -> Option<HiddenItem> {
+        match char {
+            'C' => Some(HiddenItem::Upgrade(UpgradeType::Bomb)),
+            'F' => Some(HiddenItem::Upgrade(UpgradeType::Fire)),
+            'S' => Some(HiddenItem::Upgrade(UpgradeType::Speed)),
+            '.' => Some(HiddenItem::Nothing),
+            'E' => Some(HiddenItem::Exit),
+            _ => None,
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            HiddenItem::Upgrade(UpgradeType::Bomb) => 'C',
+            HiddenItem::Upgrade(UpgradeType::Fire) => 'F',
+            HiddenItem::Upgrade(UpgradeType::Speed) => 'S',
+            HiddenItem::Upgrade(UpgradeType::None) | HiddenItem::Nothing => '.',
+            HiddenItem::Exit => 'E',
+        }
+    }
+
+    /// It reads a hidden item from its name, as written in the properties of Tiled maps
+    pub fn parse(value: &str) -> Option<HiddenItem> {
+        match value {
+            "bomb" => Some(HiddenItem::Upgrade(UpgradeType::Bomb)),
+            "fire" => Some(HiddenItem::Upgrade(UpgradeType::Fire)),
+            "speed" => Some(HiddenItem::Upgrade(UpgradeType::Speed)),
+            "nothing" => Some(HiddenItem::Nothing),
+            "exit" => Some(HiddenItem::Exit),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HiddenItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HiddenItem::Upgrade(UpgradeType::Bomb) => write!(f, "bomb"),
+            HiddenItem::Upgrade(UpgradeType::Fire) => write!(f, "fire"),
+            HiddenItem::Upgrade(UpgradeType::Speed) => write!(f, "speed"),
+            HiddenItem::Upgrade(UpgradeType::None) | HiddenItem::Nothing => write!(f, "nothing"),
+            HiddenItem::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+/// It reads the hidden layer of a map file
+///
+/// Arguments:
+///
+/// * `lines`: The lines of the layer, one row of the map each
+///
+/// Returns:
+///
+/// The hidden item of each cell that has one, or the index of the line with an unknown character
+/// and the error
+pub fn read_hidden_layer(lines: &[&str]) -> Result<HashMap<IVec2, HiddenItem>, (usize, String)> {
+    let mut hidden = HashMap::new();
+    for (z, line) in lines.iter().enumerate() {
+        for (x, char) in line.chars().enumerate() {
+            if char == RANDOM_DROP_CHAR {
+                continue;
+            }
+            let item = HiddenItem::from_char(char)
+                .ok_or_else(|| (z, format!("unknown hidden item {:?}", char)))?;
+            hidden.insert(IVec2::new(x as i32, z as i32), item);
+        }
+    }
+    Ok(hidden)
+}
+
+/// It writes the hidden items as the rows of the hidden layer, trailing random cells are left out
+///
+/// Arguments:
+///
+/// * `hidden`: The hidden item of each cell that has one
+///
+/// Returns:
+///
+/// The lines of the layer
+pub fn hidden_layer_lines(hidden: &HashMap<IVec2, HiddenItem>) -> Vec<String> {
+    let height = hidden.keys().map(|cell| cell.y + 1).max().unwrap_or(0);
+    (0..height)
+        .map(|z| {
+            let width = hidden
+                .keys()
+                .filter(|cell| cell.y == z)
+                .map(|cell| cell.x + 1)
+                .max()
+                .unwrap_or(0);
+            (0..width)
+                .map(|x| {
+                    hidden
+                        .get(&IVec2::new(x, z))
+                        .map_or(RANDOM_DROP_CHAR, |item| item.to_char())
+                })
+                .collect()
+        })
+        .collect()
+}
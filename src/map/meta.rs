@@ -0,0 +1,168 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{PLAYER_BOMB_AMOUNT, PLAYER_BOMB_RANGE, PLAYER_SPEED};
+
+/// Latest version of the map format, headerless maps are version 1
+pub const MAP_FORMAT_VERSION: u32 = 2;
+/// Line that separates the header from the tile grid
+pub const HEADER_END: &str = "---";
+
+/// How a level is won
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WinCondition {
+    /// Destroy every breakable and reach the flag
+    #[default]
+    ClearBreakables,
+    /// Stay alive until the time limit
+    Survive,
+}
+
+impl WinCondition {
+    pub fn parse(value: &str) -> Option<WinCondition> {
+        match value {
+            "clear_breakables" => Some(WinCondition::ClearBreakables),
+            "survive" => Some(WinCondition::Survive),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WinCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WinCondition::ClearBreakables => write!(f, "clear_breakables"),
+            WinCondition::Survive => write!(f, "survive"),
+        }
+    }
+}
+
+/// How the bombs of the player are counted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BombRule {
+    /// Every bomb placed is used up, only bomb upgrades give more
+    #[default]
+    Consumable,
+    /// The bombs are the most the player can have on the map at once, each one comes back when it
+    /// explodes
+    Capacity,
+}
+
+impl BombRule {
+    pub fn parse(value: &str) -> Option<BombRule> {
+        match value {
+            "consumable" => Some(BombRule::Consumable),
+            "capacity" => Some(BombRule::Capacity),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BombRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BombRule::Consumable => write!(f, "consumable"),
+            BombRule::Capacity => write!(f, "capacity"),
+        }
+    }
+}
+
+/// Header of a version 2 map, every field is optional and falls back to the level manifest or the
+/// default player values
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MapMeta {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub theme: Option<String>,
+    pub music: Option<String>,
+    /// Seconds to finish the level
+    pub time_limit: Option<f32>,
+    pub bombs: Option<u32>,
+    pub range: Option<f32>,
+    pub speed: Option<f32>,
+    pub win: Option<WinCondition>,
+    /// Replaces the bomb rule of the level
+    pub bomb_rule: Option<BombRule>,
+}
+
+impl MapMeta {
+    pub fn starting_bombs(&self) -> u32 {
+        self.bombs.unwrap_or(PLAYER_BOMB_AMOUNT)
+    }
+
+    pub fn starting_range(&self) -> f32 {
+        self.range.unwrap_or(PLAYER_BOMB_RANGE)
+    }
+
+    pub fn starting_speed(&self) -> f32 {
+        self.speed.unwrap_or(PLAYER_SPEED)
+    }
+
+    pub fn win_condition(&self) -> WinCondition {
+        self.win.unwrap_or_default()
+    }
+
+    /// It reads a `key: value` line of the header
+    ///
+    /// Arguments:
+    ///
+    /// * `key`: The name of the field
+    /// * `value`: The value of the field, already trimmed
+    ///
+    /// Returns:
+    ///
+    /// An error message if the key is unknown or the value is invalid
+    pub fn set_field(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "name" => self.name = Some(value.to_owned()),
+            "author" => self.author = Some(value.to_owned()),
+            "theme" => self.theme = Some(value.to_owned()),
+            "music" => self.music = Some(value.to_owned()),
+            "time_limit" => self.time_limit = Some(parse_number(key, value)?),
+            "bombs" => self.bombs = Some(parse_number(key, value)?),
+            "range" => self.range = Some(parse_number(key, value)?),
+            "speed" => self.speed = Some(parse_number(key, value)?),
+            "win" => {
+                self.win = Some(
+                    WinCondition::parse(value)
+                        .ok_or_else(|| format!("unknown win condition {:?}", value))?,
+                )
+            }
+            "bomb_rule" => {
+                self.bomb_rule = Some(
+                    BombRule::parse(value)
+                        .ok_or_else(|| format!("unknown bomb rule {:?}", value))?,
+                )
+            }
+            _ => return Err(format!("unknown header field {:?}", key)),
+        }
+        Ok(())
+    }
+
+    /// It writes the header lines of the fields that are set, without the version and the separator
+    pub fn to_lines(&self) -> Vec<String> {
+        let fields = [
+            ("name", self.name.clone()),
+            ("author", self.author.clone()),
+            ("theme", self.theme.clone()),
+            ("music", self.music.clone()),
+            ("time_limit", self.time_limit.map(|value| value.to_string())),
+            ("bombs", self.bombs.map(|value| value.to_string())),
+            ("range", self.range.map(|value| value.to_string())),
+            ("speed", self.speed.map(|value| value.to_string())),
+            ("win", self.win.map(|value| value.to_string())),
+            ("bomb_rule", self.bomb_rule.map(|value| value.to_string())),
+        ];
+        fields
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| format!("{}: {}", key, value)))
+            .collect()
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {:?} for {}", value, key))
+}
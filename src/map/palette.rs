@@ -0,0 +1,170 @@
+use std::{fmt, fs, path::Path};
+
+use bevy::{prelude::Resource, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{DEFAULT_FLOOR_PATH, MAP_PALETTE_PATH};
+
+use super::{theme::Theme, ObjectProps};
+
+/// Table that relates each map character with the object it spawns
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MapPalette {
+    pub tiles: HashMap<char, ObjectProps>,
+    /// Model spawned under the objects that need a floor
+    #[serde(default)]
+    pub default_floor: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(error) => write!(f, "could not read palette: {}", error),
+            PaletteError::Parse(error) => write!(f, "invalid palette: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl MapPalette {
+    /// It parses a palette written in RON
+    ///
+    /// Arguments:
+    ///
+    /// * `text`: The content of the palette file
+    ///
+    /// Returns:
+    ///
+    /// The parsed palette or the parse error
+    pub fn from_ron(text: &str) -> Result<MapPalette, PaletteError> {
+        ron::from_str(text).map_err(PaletteError::Parse)
+    }
+
+    /// It reads and parses the palette file at `path`
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: The path of the palette file, relative to the working directory
+    ///
+    /// Returns:
+    ///
+    /// The parsed palette or the error found while reading it
+    pub fn from_file(path: &str) -> Result<MapPalette, PaletteError> {
+        let text = fs::read_to_string(path).map_err(PaletteError::Io)?;
+        MapPalette::from_ron(&text)
+    }
+
+    /// It writes the palette in RON, the way the palette files are written
+    ///
+    /// Returns:
+    ///
+    /// The content of the palette file or the serialization error
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    /// It returns a copy of the palette with the `overrides` tiles added or replaced
+    ///
+    /// Arguments:
+    ///
+    /// * `overrides`: The palette whose tiles take precedence
+    ///
+    /// Returns:
+    ///
+    /// MapPalette
+    pub fn with_overrides(&self, overrides: &MapPalette) -> MapPalette {
+        let mut tiles = self.tiles.clone();
+        tiles.extend(
+            overrides
+                .tiles
+                .iter()
+                .map(|(key, props)| (*key, props.clone())),
+        );
+        MapPalette {
+            tiles,
+            default_floor: overrides
+                .default_floor
+                .clone()
+                .or_else(|| self.default_floor.clone()),
+        }
+    }
+
+    /// It returns a copy of the palette with every model path, the default floor included, resolved
+    /// through the theme
+    ///
+    /// Arguments:
+    ///
+    /// * `theme`: The theme of the level
+    ///
+    /// Returns:
+    ///
+    /// MapPalette
+    pub fn themed(&self, theme: &Theme) -> MapPalette {
+        let mut palette = self.clone();
+        for props in palette.tiles.values_mut() {
+            props.path = theme.resolve(&props.path);
+            props.autotile = props
+                .autotile
+                .as_ref()
+                .map(|set| set.map_paths(|path| theme.resolve(path)));
+            props.damaged_paths = props
+                .damaged_paths
+                .iter()
+                .map(|path| theme.resolve(path))
+                .collect();
+        }
+        palette.default_floor = Some(theme.resolve(self.default_floor()));
+        palette
+    }
+
+    pub fn get(&self, key: char) -> Option<&ObjectProps> {
+        self.tiles.get(&key)
+    }
+
+    pub fn default_floor(&self) -> &str {
+        self.default_floor.as_deref().unwrap_or(DEFAULT_FLOOR_PATH)
+    }
+}
+
+/// It returns the path of the palette override that lives next to a map file,
+/// `maps/level1.txt` becomes `maps/level1.palette.ron`
+///
+/// Arguments:
+///
+/// * `map_path`: The path of the map file
+///
+/// Returns:
+///
+/// String
+pub fn palette_override_path(map_path: &str) -> String {
+    match map_path.rsplit_once('.') {
+        Some((stem, _extension)) => format!("{}.palette.ron", stem),
+        None => format!("{}.palette.ron", map_path),
+    }
+}
+
+/// It loads the base palette and applies the overrides placed next to the map, if any
+///
+/// Arguments:
+///
+/// * `map_path`: The path of the map that will be spawned with the palette
+///
+/// Returns:
+///
+/// The palette of the map or the error found while reading it
+pub fn load_level_palette(map_path: &str) -> Result<MapPalette, PaletteError> {
+    let palette = MapPalette::from_file(MAP_PALETTE_PATH)?;
+    let override_path = palette_override_path(map_path);
+    if !Path::new(&override_path).exists() {
+        return Ok(palette);
+    }
+    let overrides = MapPalette::from_file(&override_path)?;
+    Ok(palette.with_overrides(&overrides))
+}
@@ -0,0 +1,86 @@
+use std::{fs, path::Path};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::ASSETS_FOLDER;
+
+/// A tileset variant of the models, `snow` turns `objects/tile.glb` into `objects/snow_tile.glb`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Theme {
+    /// Added to the file name of every model that has a variant
+    #[serde(default)]
+    pub prefix: String,
+}
+
+/// Every theme a level can pick, read from the themes file
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ThemeSet {
+    pub themes: HashMap<String, Theme>,
+}
+
+impl Theme {
+    /// It returns the path of the themed variant of a model, or the same path if the theme has no
+    /// variant of it
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: The path of the model relative to the assets folder, e.g. `objects/tile.glb#Scene0`
+    ///
+    /// Returns:
+    ///
+    /// String
+    pub fn resolve(&self, path: &str) -> String {
+        if self.prefix.is_empty() {
+            return path.to_owned();
+        }
+        let (folder, file) = match path.rsplit_once('/') {
+            Some((folder, file)) => (format!("{}/", folder), file),
+            None => (String::new(), path),
+        };
+        let themed = format!("{}{}{}", folder, self.prefix, file);
+
+        //Models without a variant keep the default look
+        let themed_file = themed.split('#').next().unwrap_or_default();
+        if Path::new(ASSETS_FOLDER).join(themed_file).exists() {
+            themed
+        } else {
+            path.to_owned()
+        }
+    }
+}
+
+impl ThemeSet {
+    /// It reads the themes file, panicking if it is missing or invalid
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: The path of the themes file, relative to the working directory
+    ///
+    /// Returns:
+    ///
+    /// ThemeSet
+    pub fn from_file(path: &str) -> ThemeSet {
+        let text = fs::read_to_string(path).expect("No themes file found");
+        ron::from_str(&text).expect("Invalid themes file")
+    }
+
+    /// It returns the theme called `name`, unknown themes fall back to the default models
+    ///
+    /// Arguments:
+    ///
+    /// * `name`: The name of the theme
+    ///
+    /// Returns:
+    ///
+    /// Theme
+    pub fn get(&self, name: &str) -> Theme {
+        match self.themes.get(name) {
+            Some(theme) => theme.clone(),
+            None => {
+                warn!("Unknown theme {}, using the default models", name);
+                Theme::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{collider::UpgradeType, constants::DEFAULT_OBJECT_SCALE};
+
+use super::{
+    asset::{MapAsset, MapParseError, EXIT_CHAR, NO_FLOOR_CHAR, SPAWN_CHAR},
+    hidden::HiddenItem,
+    meta::MapMeta,
+    palette::MapPalette,
+    CustomProps, ObjectProps,
+};
+
+/// Name of the tile layers, the objects are drawn over the floor
+const FLOOR_LAYER: &str = "floor";
+const OBJECTS_LAYER: &str = "objects";
+
+const FLOOR_CHAR: char = ' ';
+const BOMB_UP_CHAR: char = 'C';
+const FIRE_UP_CHAR: char = 'F';
+const SPEED_UP_CHAR: char = 'S';
+/// Tiles that are not in the palette get characters from the Unicode private use area
+const FIRST_TILED_CHAR: u32 = 0xE000;
+/// Tiled stores the flip flags in the highest bits of the tile ids
+const TILE_ID_MASK: u32 = 0x1FFF_FFFF;
+
+#[derive(Deserialize)]
+struct TiledMap {
+    width: usize,
+    height: usize,
+    #[serde(rename = "tilewidth")]
+    tile_width: f32,
+    #[serde(rename = "tileheight")]
+    tile_height: f32,
+    #[serde(default)]
+    layers: Vec<TiledLayer>,
+    #[serde(default)]
+    tilesets: Vec<TiledTileset>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+struct TiledLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    data: Vec<u32>,
+    #[serde(default)]
+    objects: Vec<TiledObject>,
+}
+
+#[derive(Deserialize)]
+struct TiledTileset {
+    #[serde(rename = "firstgid")]
+    first_gid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TiledTile>,
+}
+
+#[derive(Deserialize)]
+struct TiledTile {
+    id: u32,
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+struct TiledObject {
+    #[serde(default)]
+    name: String,
+    /// `type` before Tiled 1.9, `class` after it
+    #[serde(default, rename = "type", alias = "class")]
+    kind: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+struct TiledProperty {
+    name: String,
+    value: Value,
+}
+
+/// It finds a custom property by name
+fn property<'a>(properties: &'a [TiledProperty], name: &str) -> Option<&'a Value> {
+    properties
+        .iter()
+        .find(|property| property.name == name)
+        .map(|property| &property.value)
+}
+
+fn string_property(properties: &[TiledProperty], name: &str) -> Option<String> {
+    property(properties, name)
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+}
+
+fn bool_property(properties: &[TiledProperty], name: &str) -> Option<bool> {
+    property(properties, name).and_then(Value::as_bool)
+}
+
+fn upgrade_property(properties: &[TiledProperty]) -> Result<UpgradeType, String> {
+    match string_property(properties, "upgrade").as_deref() {
+        None | Some("") | Some("none") => Ok(UpgradeType::None),
+        Some("bomb") => Ok(UpgradeType::Bomb),
+        Some("fire") => Ok(UpgradeType::Fire),
+        Some("speed") => Ok(UpgradeType::Speed),
+        Some(upgrade) => Err(format!("unknown upgrade {:?}", upgrade)),
+    }
+}
+
+/// It converts a Tiled tile into the palette character it is spawned with. Tiles with a `char`
+/// property use that palette entry, the others become a new palette entry built from their properties
+///
+/// Arguments:
+///
+/// * `tile`: The tile of the tileset
+/// * `char`: The character given to the tile when it has no `char` property
+///
+/// Returns:
+///
+/// The character of the tile and its palette entry, if it needs a new one
+fn convert_tile(tile: &TiledTile, char: char) -> Result<(char, Option<ObjectProps>), String> {
+    if let Some(key) = string_property(&tile.properties, "char") {
+        let mut chars = key.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(key), None) => Ok((key, None)),
+            _ => Err(format!("tile {} has an invalid char {:?}", tile.id, key)),
+        };
+    }
+
+    //Tile images are renders of the models, `tile_straight.png` draws `objects/tile_straight.glb`
+    let path = match string_property(&tile.properties, "path") {
+        Some(path) => path,
+        None => {
+            let image = tile
+                .image
+                .as_deref()
+                .ok_or_else(|| format!("tile {} has no char, path or image", tile.id))?;
+            let stem = image
+                .rsplit('/')
+                .next()
+                .and_then(|file| file.split('.').next())
+                .unwrap_or(image);
+            format!("objects/{}.glb#Scene0", stem)
+        }
+    };
+    let upgrade = upgrade_property(&tile.properties)?;
+    let is_floor = bool_property(&tile.properties, "is_floor").unwrap_or(false);
+    //The same size and height `spawn_object` gives them, but through the custom spawn path
+    //so breakables, hits and animated upgrades get their components
+    let custom = if upgrade != UpgradeType::None {
+        CustomProps {
+            scale: DEFAULT_OBJECT_SCALE.to_owned() + Vec3::new(0.5, 0.5, 0.5),
+            rotation: Quat::IDENTITY,
+            sum_translation: Vec3::new(0., 0.5, 0.),
+        }
+    } else {
+        CustomProps {
+            scale: DEFAULT_OBJECT_SCALE.to_owned(),
+            rotation: Quat::IDENTITY,
+            sum_translation: Vec3::new(0., 0.1, 0.),
+        }
+    };
+    let props = ObjectProps {
+        name: string_property(&tile.properties, "name")
+            .unwrap_or_else(|| format!("Tiled#{}", tile.id)),
+        add_floor: bool_property(&tile.properties, "add_floor").unwrap_or(!is_floor),
+        path,
+        is_floor,
+        upgrade,
+        animated_rotation: bool_property(&tile.properties, "animated_rotation").unwrap_or(false),
+        breakable: bool_property(&tile.properties, "breakable").unwrap_or(false),
+        custom: Some(custom),
+        autotile: None,
+        hits: property(&tile.properties, "hits")
+            .and_then(Value::as_u64)
+            .map(|hits| hits as u32),
+        damaged_paths: Vec::new(),
+    };
+    Ok((char, Some(props)))
+}
+
+/// It reads a Tiled JSON map (`.tmj`) into the same grid the text maps are parsed into.
+///
+/// The `objects` tile layer is drawn over the `floor` one. Cells with both keep their floor tile in
+/// the floor layer of the map, so objects can stand on any floor.
+/// Objects of the object layers with the `spawn` or `exit` type are the spawn and exit points, and
+/// the `item` ones place the upgrade of their `upgrade` property. The `hidden` ones set what the
+/// breakable of their cell hides with their `item` property (`bomb`, `fire`, `speed`, `nothing` or
+/// `exit`). Map properties with the names of the map header fields fill the header.
+///
+/// Arguments:
+///
+/// * `bytes`: The content of the Tiled file
+///
+/// Returns:
+///
+/// The map, with the palette entries of the tiles that are not in the palette, or the import error
+pub fn import_tiled(bytes: &[u8]) -> Result<MapAsset, MapParseError> {
+    read_tiled(bytes).map_err(MapParseError::Tiled)
+}
+
+fn read_tiled(bytes: &[u8]) -> Result<MapAsset, String> {
+    let tiled: TiledMap = serde_json::from_slice(bytes).map_err(|error| error.to_string())?;
+    if tiled.tile_width <= 0.0 || tiled.tile_height <= 0.0 {
+        return Err(String::from("tiles have no size"));
+    }
+    if tiled.width == 0 || tiled.height == 0 {
+        return Err(String::from("map has no size"));
+    }
+
+    //Every tile id of the tilesets with its character
+    let mut chars: HashMap<u32, char> = HashMap::new();
+    let mut palette = MapPalette::default();
+    let mut next_char = FIRST_TILED_CHAR;
+    for tileset in tiled.tilesets.iter() {
+        if let Some(source) = &tileset.source {
+            return Err(format!(
+                "external tileset {} is not supported, embed it in the map",
+                source
+            ));
+        }
+        for tile in tileset.tiles.iter() {
+            let new_char = char::from_u32(next_char).ok_or("too many tiles")?;
+            let (char, props) = convert_tile(tile, new_char)?;
+            if let Some(props) = props {
+                palette.tiles.insert(char, props);
+                next_char += 1;
+            }
+            chars.insert(tileset.first_gid + tile.id, char);
+        }
+    }
+
+    let mut rows = vec![vec![FLOOR_CHAR; tiled.width]; tiled.height];
+    let mut floor_rows = vec![vec![NO_FLOOR_CHAR; tiled.width]; tiled.height];
+    let mut spawn_points = Vec::new();
+    let mut exit_points = Vec::new();
+    let mut hidden = HashMap::new();
+
+    //The floor goes first so the objects are drawn over it
+    let tile_layers = [FLOOR_LAYER, OBJECTS_LAYER].into_iter().flat_map(|name| {
+        tiled
+            .layers
+            .iter()
+            .filter(move |layer| layer.kind == "tilelayer" && layer.name == name)
+    });
+    for layer in tile_layers {
+        if layer.data.len() != tiled.width * tiled.height {
+            return Err(format!(
+                "layer {} has {} tiles, expected {}",
+                layer.name,
+                layer.data.len(),
+                tiled.width * tiled.height
+            ));
+        }
+        for (index, gid) in layer.data.iter().enumerate() {
+            let gid = gid & TILE_ID_MASK;
+            if gid == 0 {
+                continue;
+            }
+            let char = *chars
+                .get(&gid)
+                .ok_or_else(|| format!("tile {} of layer {} has no char", gid, layer.name))?;
+            let (x, z) = (index % tiled.width, index / tiled.width);
+            rows[z][x] = char;
+            if layer.name == FLOOR_LAYER {
+                floor_rows[z][x] = char;
+            }
+        }
+    }
+
+    let objects = tiled
+        .layers
+        .iter()
+        .filter(|layer| layer.kind == "objectgroup")
+        .flat_map(|layer| layer.objects.iter());
+    for object in objects {
+        //Tile objects are anchored at their bottom left corner, the others at the top left one
+        let center_y = match object.gid {
+            Some(_) => object.y - object.height / 2.0,
+            None => object.y + object.height / 2.0,
+        };
+        let cell = IVec2::new(
+            ((object.x + object.width / 2.0) / tiled.tile_width).floor() as i32,
+            (center_y / tiled.tile_height).floor() as i32,
+        );
+        if cell.x < 0
+            || cell.y < 0
+            || cell.x as usize >= tiled.width
+            || cell.y as usize >= tiled.height
+        {
+            return Err(format!("object {:?} is outside the map", object.name));
+        }
+        match object.kind.as_str() {
+            "spawn" => spawn_points.push(cell),
+            "exit" => exit_points.push(cell),
+            "hidden" => {
+                let item = string_property(&object.properties, "item").unwrap_or_default();
+                let item = HiddenItem::parse(&item).ok_or_else(|| {
+                    format!("hidden {:?} has an unknown item {:?}", object.name, item)
+                })?;
+                hidden.insert(cell, item);
+            }
+            "item" => {
+                let char = match upgrade_property(&object.properties)? {
+                    UpgradeType::Bomb => BOMB_UP_CHAR,
+                    UpgradeType::Fire => FIRE_UP_CHAR,
+                    UpgradeType::Speed => SPEED_UP_CHAR,
+                    UpgradeType::None => {
+                        return Err(format!("item {:?} has no upgrade", object.name))
+                    }
+                };
+                rows[cell.y as usize][cell.x as usize] = char;
+            }
+            _ => {}
+        }
+    }
+
+    let mut meta = MapMeta::default();
+    for property in tiled.properties.iter() {
+        let value = match &property.value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        meta.set_field(&property.name, &value)?;
+    }
+
+    //Points are written with their characters so they are read like the text maps
+    for cell in spawn_points.iter() {
+        rows[cell.y as usize][cell.x as usize] = SPAWN_CHAR;
+    }
+    for cell in exit_points.iter() {
+        rows[cell.y as usize][cell.x as usize] = EXIT_CHAR;
+    }
+    //The floor layer only keeps the floors that are covered, the others are already on the grid
+    for (row, floor_row) in rows.iter().zip(floor_rows.iter_mut()) {
+        for (char, floor) in row.iter().zip(floor_row.iter_mut()) {
+            if char == floor {
+                *floor = NO_FLOOR_CHAR;
+            }
+        }
+    }
+    if floor_rows
+        .iter()
+        .flatten()
+        .all(|char| *char == NO_FLOOR_CHAR)
+    {
+        floor_rows.clear();
+    }
+
+    let mut map = MapAsset::from_rows(meta, rows);
+    map.floor_rows = floor_rows;
+    map.hidden = hidden;
+    map.palette = palette;
+    Ok(map)
+}
+
+#[derive(Default)]
+pub struct TiledMapLoader;
+
+impl AssetLoader for TiledMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let map = import_tiled(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmj"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A 4x3 map: a border of `$`, a `%` floor on the middle row and a crate from its image
+    fn tiled_map(width: usize, objects_data: Vec<u32>) -> Vec<u8> {
+        json!({
+            "width": width,
+            "height": 3,
+            "tilewidth": 16,
+            "tileheight": 16,
+            "properties": [{ "name": "name", "type": "string", "value": "Tiled Test" }],
+            "tilesets": [{
+                "firstgid": 1,
+                "tiles": [
+                    { "id": 0, "properties": [{ "name": "char", "value": "$" }] },
+                    { "id": 1, "properties": [{ "name": "char", "value": "%" }] },
+                    {
+                        "id": 2,
+                        "image": "tiles/crate.png",
+                        "properties": [
+                            { "name": "breakable", "value": true },
+                            { "name": "hits", "value": 2 }
+                        ]
+                    }
+                ]
+            }],
+            "layers": [
+                { "type": "tilelayer", "name": "floor", "data": [0, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0, 0] },
+                { "type": "tilelayer", "name": "objects", "data": objects_data },
+                {
+                    "type": "objectgroup",
+                    "name": "points",
+                    "objects": [
+                        { "type": "spawn", "x": 16, "y": 16, "width": 16, "height": 16 },
+                        {
+                            "class": "hidden",
+                            "x": 32,
+                            "y": 16,
+                            "width": 16,
+                            "height": 16,
+                            "properties": [{ "name": "item", "value": "bomb" }]
+                        }
+                    ]
+                }
+            ]
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn objects_data() -> Vec<u32> {
+        vec![1, 1, 1, 1, 1, 0, 3, 1, 1, 1, 1, 1]
+    }
+
+    #[test]
+    fn tiles_points_and_properties_are_converted() {
+        let map = import_tiled(&tiled_map(4, objects_data())).unwrap();
+        let crate_char = char::from_u32(FIRST_TILED_CHAR).unwrap();
+        assert_eq!(map.meta.name.as_deref(), Some("Tiled Test"));
+        assert_eq!(map.rows[0], vec!['$'; 4]);
+        assert_eq!(map.rows[1], vec!['$', ' ', crate_char, '$']);
+        assert_eq!(map.spawn_points, vec![IVec2::new(1, 1)]);
+
+        //The floor stays under the crate and the spawn
+        assert_eq!(map.floor_at(IVec2::new(1, 1)), Some('%'));
+        assert_eq!(map.floor_at(IVec2::new(2, 1)), Some('%'));
+        assert_eq!(map.floor_at(IVec2::new(0, 1)), None);
+
+        let crate_props = map.palette.get(crate_char).unwrap();
+        assert_eq!(crate_props.path, "objects/crate.glb#Scene0");
+        assert!(crate_props.breakable);
+        assert_eq!(crate_props.hits(), 2);
+        assert_eq!(
+            map.hidden.get(&IVec2::new(2, 1)),
+            Some(&HiddenItem::Upgrade(UpgradeType::Bomb))
+        );
+    }
+
+    #[test]
+    fn tiles_are_spawned_as_custom_objects() {
+        let item: TiledTile = serde_json::from_value(json!({
+            "id": 3,
+            "image": "tiles/bombup.png",
+            "properties": [
+                { "name": "upgrade", "value": "bomb" },
+                { "name": "animated_rotation", "value": true },
+                { "name": "breakable", "value": true }
+            ]
+        }))
+        .unwrap();
+        let (_, item_props) = convert_tile(&item, 'x').unwrap();
+        let item_props = item_props.unwrap();
+        //With a custom entry `spawn_props` goes to `spawn_custom`, which adds the item and breakable components
+        assert!(item_props.add_floor);
+        let custom = item_props.custom.as_ref().unwrap();
+        assert_eq!(item_props.upgrade, UpgradeType::Bomb);
+        assert!(item_props.breakable && item_props.animated_rotation);
+        assert_eq!(custom.sum_translation, Vec3::new(0., 0.5, 0.));
+
+        let map = import_tiled(&tiled_map(4, objects_data())).unwrap();
+        let crate_props = map
+            .palette
+            .get(char::from_u32(FIRST_TILED_CHAR).unwrap())
+            .unwrap();
+        assert!(crate_props.add_floor && crate_props.breakable);
+        assert_eq!(
+            crate_props.custom.as_ref().unwrap().scale,
+            DEFAULT_OBJECT_SCALE.to_owned()
+        );
+    }
+
+    #[test]
+    fn mismatched_sizes_are_errors() {
+        assert!(matches!(
+            import_tiled(&tiled_map(0, objects_data())),
+            Err(MapParseError::Tiled(_))
+        ));
+        let mut short_data = objects_data();
+        short_data.pop();
+        assert!(matches!(
+            import_tiled(&tiled_map(4, short_data)),
+            Err(MapParseError::Tiled(_))
+        ));
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::collider::UpgradeType;
+
+use super::{
+    asset::MapAsset,
+    grid::{CellContent, GridMap},
+    hidden::HiddenItem,
+    meta::{BombRule, WinCondition},
+    palette::MapPalette,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found while validating a map
+#[derive(Clone, Debug)]
+pub struct MapIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for MapIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
+
+impl MapIssue {
+    pub fn warning(message: String) -> MapIssue {
+        MapIssue {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+
+    pub fn error(message: String) -> MapIssue {
+        MapIssue {
+            severity: Severity::Error,
+            message,
+        }
+    }
+}
+
+/// It checks that every character of the map, in the grid and the floor layer, can be spawned with
+/// the palette
+///
+/// Arguments:
+///
+/// * `map`: The parsed map
+/// * `palette`: The palette the map is spawned with
+///
+/// Returns:
+///
+/// The errors found, an empty list means the map can be spawned
+pub fn palette_issues(map: &MapAsset, palette: &MapPalette) -> Vec<MapIssue> {
+    let mut issues = Vec::new();
+    //Characters that spawn_map_object can not spawn
+    for (cell, char) in map.cells() {
+        if palette.get(char).is_none() {
+            issues.push(MapIssue::error(format!(
+                "unknown character {:?} at line {}, column {}",
+                char,
+                cell.y + 1,
+                cell.x + 1
+            )));
+        }
+    }
+
+    //The floor layer can only have floor tiles, inside the object layer
+    for (cell, char) in map.floor_cells() {
+        let message = match palette.get(char) {
+            None => "unknown floor character",
+            Some(props) if !props.is_floor => "floor layer character that is not a floor",
+            Some(_) if map.get(cell).is_none() => "floor layer character outside the map",
+            Some(_) => continue,
+        };
+        issues.push(MapIssue::error(format!(
+            "{} {:?} at line {}, column {}",
+            message,
+            char,
+            cell.y + 1,
+            cell.x + 1
+        )));
+    }
+    issues
+}
+
+/// It checks a map against the palette and the rules of the game
+///
+/// Arguments:
+///
+/// * `map`: The parsed map
+/// * `palette`: The palette the map is spawned with
+/// * `starting_bombs`: The amount of bombs the player starts with
+/// * `bomb_rule`: How the bombs of the player are counted
+///
+/// Returns:
+///
+/// Every issue found, an empty list means the map is valid
+pub fn validate_map(
+    map: &MapAsset,
+    palette: &MapPalette,
+    starting_bombs: u32,
+    bomb_rule: BombRule,
+) -> Vec<MapIssue> {
+    let mut issues = Vec::new();
+    let width = map.width();
+
+    issues.extend(palette_issues(map, palette));
+
+    //Every row should have the same length
+    for (z, row) in map.rows.iter().enumerate() {
+        if row.len() != width {
+            issues.push(MapIssue::error(format!(
+                "line {} has {} tiles, expected {}",
+                z + 1,
+                row.len(),
+                width
+            )));
+        }
+    }
+
+    //A survival level only ends when the time runs out
+    if map.meta.win_condition() == WinCondition::Survive && map.meta.time_limit.is_none() {
+        issues.push(MapIssue::error(String::from(
+            "survive levels need a time_limit in the header",
+        )));
+    }
+
+    let grid_map = GridMap::from_map(map, palette);
+
+    //The outer ring of the map must be closed by walls
+    let missing_border: Vec<IVec2> = grid_map
+        .iter()
+        .filter(|(cell, _)| {
+            cell.x == 0
+                || cell.y == 0
+                || cell.x as usize == grid_map.width() - 1
+                || cell.y as usize == grid_map.height() - 1
+        })
+        .filter(|(_, content)| *content != CellContent::Wall)
+        .map(|(cell, _)| cell)
+        .collect();
+    for cell in missing_border {
+        issues.push(MapIssue::error(format!(
+            "missing border wall at line {}, column {}",
+            cell.y + 1,
+            cell.x + 1
+        )));
+    }
+
+    //Hidden items only come out of breakables
+    let mut hidden: Vec<(&IVec2, &HiddenItem)> = map.hidden.iter().collect();
+    hidden.sort_by_key(|(cell, _)| (cell.y, cell.x));
+    for (cell, item) in hidden {
+        if grid_map.get(*cell) != Some(CellContent::Breakable) {
+            issues.push(MapIssue::error(format!(
+                "hidden {} at line {}, column {} is not under a breakable",
+                item,
+                cell.y + 1,
+                cell.x + 1
+            )));
+        }
+    }
+
+    //Breakables that need bombs, one per hit they take, items are picked up instead
+    let breakables: usize = map
+        .cells()
+        .filter(|(cell, _)| grid_map.get(*cell) == Some(CellContent::Breakable))
+        .filter_map(|(_, char)| palette.get(char))
+        .map(|props| props.hits() as usize)
+        .sum();
+    let bomb_ups = grid_map
+        .iter()
+        .filter(|(_, content)| *content == CellContent::Item(UpgradeType::Bomb))
+        .count()
+        + map
+            .hidden
+            .values()
+            .filter(|item| **item == HiddenItem::Upgrade(UpgradeType::Bomb))
+            .count();
+    let available_bombs = starting_bombs as usize + bomb_ups;
+    match bomb_rule {
+        BombRule::Consumable if breakables > available_bombs => {
+            issues.push(MapIssue::warning(format!(
+                "breakables need {} bombs but only {} ({} starting, {} from items), the level may be unwinnable",
+                breakables, available_bombs, starting_bombs, bomb_ups
+            )));
+        }
+        //Capacity bombs come back, a single one clears the map
+        BombRule::Capacity if breakables > 0 && available_bombs == 0 => {
+            issues.push(MapIssue::warning(String::from(
+                "the bomb capacity is 0 and no bomb upgrade is placed, the level is unwinnable",
+            )));
+        }
+        _ => {}
+    }
+
+    //Every cell that is not a wall should be reachable once the breakables are destroyed
+    let spawn = map.spawn_cell();
+    match grid_map.get(spawn) {
+        Some(content) if content.is_walkable() => {
+            let reached = grid_map.flood_fill(spawn, |content| {
+                content != CellContent::Wall && content != CellContent::Empty
+            });
+            let unreachable: Vec<(IVec2, CellContent)> = grid_map
+                .iter()
+                .filter(|(cell, content)| {
+                    *content != CellContent::Wall
+                        && *content != CellContent::Empty
+                        && !reached.contains(cell)
+                })
+                .collect();
+            let blocked_objectives = unreachable
+                .iter()
+                .filter(|(_, content)| *content != CellContent::Floor)
+                .count();
+            if blocked_objectives > 0 {
+                issues.push(MapIssue::error(format!(
+                    "{} breakables or items can not be reached from the spawn",
+                    blocked_objectives
+                )));
+            }
+            if unreachable.len() > blocked_objectives {
+                issues.push(MapIssue::warning(format!(
+                    "{} floor tiles can not be reached from the spawn",
+                    unreachable.len() - blocked_objectives
+                )));
+            }
+
+            //Other players and the flag must share the area of the first spawn
+            let hidden_exits = map.hidden_exits();
+            let markers = map
+                .spawn_points
+                .iter()
+                .map(|cell| ("spawn", cell))
+                .chain(map.exit_points.iter().map(|cell| ("exit", cell)))
+                .chain(hidden_exits.iter().map(|cell| ("hidden exit", cell)));
+            for (marker, cell) in markers {
+                if !reached.contains(cell) {
+                    issues.push(MapIssue::error(format!(
+                        "{} at line {}, column {} can not be reached from the first spawn",
+                        marker,
+                        cell.y + 1,
+                        cell.x + 1
+                    )));
+                }
+            }
+        }
+        _ => {
+            issues.push(MapIssue::error(format!(
+                "spawn at line {}, column {} is not walkable",
+                spawn.y + 1,
+                spawn.x + 1
+            )));
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MAP_PALETTE_PATH;
+
+    fn validate_text(text: &str, bombs: u32, bomb_rule: BombRule) -> Vec<MapIssue> {
+        let map = MapAsset::parse(text).unwrap();
+        let palette = MapPalette::from_file(MAP_PALETTE_PATH).unwrap();
+        validate_map(&map, &palette, bombs, bomb_rule)
+    }
+
+    fn has_issue(issues: &[MapIssue], severity: Severity, message: &str) -> bool {
+        issues
+            .iter()
+            .any(|issue| issue.severity == severity && issue.message.contains(message))
+    }
+
+    #[test]
+    fn closed_map_is_valid() {
+        let issues = validate_text("$$$$$\n$P @$\n$$$$$", 1, BombRule::Consumable);
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn ragged_rows_are_errors() {
+        let issues = validate_text("$$$$$\n$P $\n$$$$$", 0, BombRule::Consumable);
+        assert!(has_issue(
+            &issues,
+            Severity::Error,
+            "line 2 has 4 tiles, expected 5"
+        ));
+    }
+
+    #[test]
+    fn open_border_is_an_error() {
+        let issues = validate_text("$$$$$\n$P   \n$$$$$", 0, BombRule::Consumable);
+        assert!(has_issue(
+            &issues,
+            Severity::Error,
+            "missing border wall at line 2, column 5"
+        ));
+    }
+
+    #[test]
+    fn spawn_on_a_wall_is_an_error() {
+        let mut map = MapAsset::parse("$$$$$\n$P  $\n$$$$$").unwrap();
+        map.spawn_points = vec![IVec2::new(0, 1)];
+        let palette = MapPalette::from_file(MAP_PALETTE_PATH).unwrap();
+        let issues = validate_map(&map, &palette, 0, BombRule::Consumable);
+        assert!(has_issue(
+            &issues,
+            Severity::Error,
+            "spawn at line 2, column 1 is not walkable"
+        ));
+    }
+
+    #[test]
+    fn unreachable_regions_are_reported() {
+        //Floor behind walls is a warning, a breakable or an exit there can't be finished
+        let issues = validate_text("$$$$$$$\n$P $  $\n$$$$$$$", 0, BombRule::Consumable);
+        assert!(has_issue(
+            &issues,
+            Severity::Warning,
+            "2 floor tiles can not be reached"
+        ));
+        let issues = validate_text("$$$$$$$\n$P $@E$\n$$$$$$$", 1, BombRule::Consumable);
+        assert!(has_issue(
+            &issues,
+            Severity::Error,
+            "1 breakables or items can not be reached"
+        ));
+        assert!(has_issue(
+            &issues,
+            Severity::Error,
+            "exit at line 2, column 6 can not be reached"
+        ));
+    }
+
+    #[test]
+    fn breakables_are_checked_against_the_bombs() {
+        let map = "$$$$$$\n$P@@C$\n$$$$$$";
+        //Two breakables, one starting bomb and a bomb upgrade
+        assert!(!has_issue(
+            &validate_text(map, 1, BombRule::Consumable),
+            Severity::Warning,
+            "breakables need"
+        ));
+        assert!(has_issue(
+            &validate_text(map, 0, BombRule::Consumable),
+            Severity::Warning,
+            "breakables need 2 bombs but only 1"
+        ));
+
+        //A single capacity bomb clears every breakable
+        let map = "$$$$$\n$P@@$\n$$$$$";
+        assert!(validate_text(map, 1, BombRule::Capacity).is_empty());
+        assert!(has_issue(
+            &validate_text(map, 0, BombRule::Capacity),
+            Severity::Warning,
+            "the bomb capacity is 0"
+        ));
+    }
+}
@@ -29,7 +29,8 @@ impl Plugin for MenuPlugin {
     }
 }
 
-/// When any button is pressed, despawn the menu UI and set the game state to `Gameplay`
+/// When any button is pressed, despawn the menu UI and set the game state to `Loading`, or to `Editor`
+/// if the button is F2
 ///
 /// Arguments:
 ///
@@ -56,7 +57,11 @@ fn any_button_pressed(
                     String::from("audios/sfx/menu_click.ogg"),
                 );
 
-                game_state.set(GameState::Gameplay).unwrap();
+                if ev.key_code == Some(KeyCode::F2) {
+                    game_state.set(GameState::Editor).unwrap();
+                } else {
+                    game_state.set(GameState::Loading).unwrap();
+                }
             }
             ButtonState::Released => {}
         }
@@ -4,7 +4,8 @@ use bevy_kira_audio::DynamicAudioChannels;
 use crate::{
     audio::play_sfx,
     constants::{HEIGHT, SFX_AUDIO_CHANNEL, WIDTH},
-    GameState, Level,
+    level::{CurrentLevel, LevelManifest},
+    GameState,
 };
 
 #[derive(Component)]
@@ -29,7 +30,8 @@ fn enter_pressed(
     keyboard: Res<Input<KeyCode>>,
     next_level_root: Query<Entity, With<NextLevelUI>>,
     mut game_state: ResMut<State<GameState>>,
-    mut level_state: ResMut<State<Level>>,
+    manifest: Res<LevelManifest>,
+    mut current_level: ResMut<CurrentLevel>,
     asset_server: Res<AssetServer>,
     mut audio: ResMut<DynamicAudioChannels>,
 ) {
@@ -37,18 +39,12 @@ fn enter_pressed(
         let root_entity = next_level_root.single();
         commands.entity(root_entity).despawn_recursive();
 
-        //TODO: Improve this level update
-        if level_state.current().to_owned() == Level::Level1 {
-            level_state.set(Level::Level2).unwrap();
-        }
-        if level_state.current().to_owned() == Level::Level2 {
-            level_state.set(Level::Level3).unwrap();
-        }
-
-        if level_state.current().to_owned() != Level::Level3 {
-            game_state.set(GameState::Gameplay).unwrap();
+        //Play the next level of the manifest, or go back to the menu after the last one
+        if let Some(next_level) = manifest.next(&current_level) {
+            *current_level = next_level;
+            game_state.set(GameState::Loading).unwrap();
         } else {
-            level_state.set(Level::Level1).unwrap();
+            *current_level = CurrentLevel::default();
             game_state.set(GameState::Menu).unwrap();
         }
         play_sfx(
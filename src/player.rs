@@ -5,7 +5,15 @@ use bevy_rapier3d::prelude::{
     Collider, ExternalForce, KinematicCharacterController, Restitution, RigidBody,
 };
 
-use crate::{constants::BOMB_SPAWN_DELAY, GameState};
+use crate::{
+    constants::{
+        BOMB_SPAWN_DELAY, EXPLOSION_DAMAGE, PLAYER_BLINK_INTERVAL, PLAYER_INVULNERABILITY_TIME,
+        PLAYER_LIFE,
+    },
+    flame::Flame,
+    map::{asset::MapAsset, grid::GridMap, CurrentMap},
+    GameState,
+};
 
 pub struct PlayerPlugin;
 
@@ -18,10 +26,21 @@ pub struct Player {
     pub bomb_amount: u32,
 }
 
+/// Frames after a hit where the player blinks and flames don't damage it
+#[derive(Component)]
+pub struct Invulnerable {
+    pub timer: Timer,
+}
+
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_enter(GameState::Gameplay).with_system(spawn_player))
-            .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(player_movement))
+            .add_system_set(
+                SystemSet::on_update(GameState::Gameplay)
+                    .with_system(player_movement)
+                    .with_system(flame_damage_player)
+                    .with_system(blink_invulnerable_player),
+            )
             .add_system_set(SystemSet::on_exit(GameState::Gameplay).with_system(despawn_player));
     }
 }
@@ -79,13 +98,24 @@ fn player_movement(
 ///
 /// * `commands`: Commands - This is the command buffer that we will use to spawn the player.
 /// * `asset_server`: Res<AssetServer>
-fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// * `maps`: Res<Assets<MapAsset>>
+/// * `current_map`: Res<CurrentMap> - The map that sets the spawn point and whose header sets the
+/// starting bombs, range and speed
+fn spawn_player(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<MapAsset>>,
+    current_map: Res<CurrentMap>,
+) {
+    let map = maps.get(&current_map.handle).expect("Map is not loaded");
+    let meta = &map.meta;
+
     // cube
     commands
         .spawn(SceneBundle {
             scene: asset_server.load("objects/enemy_ufoRedWeapon.glb#Scene0"),
             transform: Transform {
-                translation: Vec3::new(0.1, 0.4, -0.1),
+                translation: map.spawn_position(0),
                 scale: Vec3::new(0.5, 0.8, 0.5),
                 ..Default::default()
             },
@@ -101,14 +131,79 @@ fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
         .insert(Restitution::coefficient(0.1))
         .insert(Name::new("Player"))
         .insert(Player {
-            speed: 1.0,
-            bomb_amount: 10,
+            speed: meta.starting_speed(),
+            bomb_amount: meta.starting_bombs(),
             bomb_delay: Timer::new(Duration::from_millis(BOMB_SPAWN_DELAY), TimerMode::Once),
-            bomb_range: 1.0,
-            life: 2.0,
+            bomb_range: meta.starting_range(),
+            life: PLAYER_LIFE,
         });
 }
 
+/// It damages the player when a flame is on its cell, the player becomes invulnerable for a while
+/// after a hit and the game is over once it has no life left
+///
+/// Arguments:
+///
+/// * `commands`: Commands
+/// * `player_query`: Query<(Entity, &mut Player, &Transform), Without<Invulnerable>>
+/// * `flame_query`: Query<&mut Flame>
+/// * `game_state`: ResMut<State<GameState>>
+fn flame_damage_player(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &mut Player, &Transform), Without<Invulnerable>>,
+    mut flame_query: Query<&mut Flame>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    let (player_entity, mut player, transform) = match player_query.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let player_cell = GridMap::world_to_cell(transform.translation);
+
+    for mut flame in flame_query.iter_mut() {
+        if flame.cell != player_cell || flame.damaged.contains(&player_entity) {
+            continue;
+        }
+        flame.damaged.push(player_entity);
+        player.life -= EXPLOSION_DAMAGE;
+
+        if player.life <= 0.0 {
+            //The state may already be changing if the level ended this frame
+            let _ = game_state.set(GameState::GameOver);
+        } else {
+            commands.entity(player_entity).insert(Invulnerable {
+                timer: Timer::from_seconds(PLAYER_INVULNERABILITY_TIME, TimerMode::Once),
+            });
+        }
+        //Only one flame damages the player at a time
+        return;
+    }
+}
+
+/// It blinks the player while it is invulnerable, and shows it again once the invulnerability ends
+///
+/// Arguments:
+///
+/// * `commands`: Commands
+/// * `player_query`: Query<(Entity, &mut Invulnerable, &mut Visibility), With<Player>>
+/// * `time`: Res<Time>
+fn blink_invulnerable_player(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &mut Invulnerable, &mut Visibility), With<Player>>,
+    time: Res<Time>,
+) {
+    for (player_entity, mut invulnerable, mut visibility) in player_query.iter_mut() {
+        invulnerable.timer.tick(time.delta());
+        if invulnerable.timer.finished() {
+            visibility.is_visible = true;
+            commands.entity(player_entity).remove::<Invulnerable>();
+            continue;
+        }
+        let blinks = (invulnerable.timer.elapsed_secs() / PLAYER_BLINK_INTERVAL) as u32;
+        visibility.is_visible = blinks % 2 == 1;
+    }
+}
+
 fn despawn_player(mut commands: Commands, mut player_query: Query<(&Player, Entity)>) {
     let (_player, player_entity) = player_query.single_mut();
 
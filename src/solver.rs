@@ -0,0 +1,349 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    collider::UpgradeType,
+    constants::UPGRADE_FIRE_RANGE,
+    map::{
+        asset::MapAsset,
+        grid::{CellContent, GridMap},
+        meta::BombRule,
+        palette::MapPalette,
+    },
+};
+
+/// Starting conditions of the player for the solver
+#[derive(Clone, Debug)]
+pub struct SolverConfig {
+    pub spawn: IVec2,
+    pub bombs: u32,
+    pub range: f32,
+    /// With the capacity rule `bombs` is the most bombs on the map at once, never used up
+    pub bomb_rule: BombRule,
+    /// Amount of states searched before giving up
+    pub max_states: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolverResult {
+    /// Every breakable can be cleared, `bombs` is the minimum amount of bombs used
+    Solvable { bombs: u32 },
+    /// The starting bombs are not enough, `bombs_needed` is the amount needed without the limit or
+    /// `None` if some breakables can never be cleared
+    Unsolvable { bombs_needed: Option<u32> },
+    /// The search was too big to give an answer
+    LimitReached,
+}
+
+enum SearchResult {
+    Found(u32),
+    Exhausted,
+    LimitReached,
+}
+
+/// Set of objective indexes
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+struct ObjectiveSet(Vec<u64>);
+
+impl ObjectiveSet {
+    fn new(size: usize) -> ObjectiveSet {
+        ObjectiveSet(vec![0; size / 64 + 1])
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|bits| bits.count_ones() as usize).sum()
+    }
+}
+
+/// Objectives destroyed or picked up, the items picked up by the player and the explosions taken by
+/// each objective still standing
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct SearchState {
+    removed: ObjectiveSet,
+    collected: ObjectiveSet,
+    damage: Vec<u32>,
+}
+
+/// Breakables and items of the map that must be removed to finish the level, with the explosions each
+/// one takes
+struct Puzzle {
+    grid_map: GridMap,
+    objectives: Vec<(IVec2, CellContent)>,
+    objective_index: HashMap<IVec2, usize>,
+    objective_hits: Vec<u32>,
+    config: SolverConfig,
+}
+
+/// It searches the bomb placements that clear every breakable of the map. Items are picked up as soon
+/// as they can be reached and the upgrades hidden or dropped by breakables are not taken into account
+///
+/// Arguments:
+///
+/// * `map`: The parsed map
+/// * `palette`: The palette the map is spawned with
+/// * `config`: The starting conditions of the player
+///
+/// Returns:
+///
+/// SolverResult
+pub fn solve(map: &MapAsset, palette: &MapPalette, config: &SolverConfig) -> SolverResult {
+    let hits: HashMap<IVec2, u32> = map
+        .cells()
+        .filter_map(|(cell, char)| palette.get(char).map(|props| (cell, props.hits())))
+        .collect();
+    let puzzle = Puzzle::new(GridMap::from_map(map, palette), &hits, config.clone());
+    match puzzle.search(true) {
+        SearchResult::Found(bombs) => SolverResult::Solvable { bombs },
+        SearchResult::LimitReached => SolverResult::LimitReached,
+        SearchResult::Exhausted => match puzzle.search(false) {
+            SearchResult::Found(bombs) => SolverResult::Unsolvable {
+                bombs_needed: Some(bombs),
+            },
+            SearchResult::Exhausted => SolverResult::Unsolvable { bombs_needed: None },
+            SearchResult::LimitReached => SolverResult::LimitReached,
+        },
+    }
+}
+
+impl Puzzle {
+    /// It lists the objectives of the grid, `hits` is the amount of explosions each breakable takes,
+    /// breakables missing from it and items take one
+    fn new(grid_map: GridMap, hits: &HashMap<IVec2, u32>, config: SolverConfig) -> Puzzle {
+        let objectives: Vec<(IVec2, CellContent)> = grid_map
+            .iter()
+            .filter(|(_, content)| matches!(content, CellContent::Breakable | CellContent::Item(_)))
+            .collect();
+        let objective_hits = objectives
+            .iter()
+            .map(|(cell, content)| match content {
+                CellContent::Breakable => hits.get(cell).copied().unwrap_or(1).max(1),
+                _ => 1,
+            })
+            .collect();
+        let objective_index = objectives
+            .iter()
+            .enumerate()
+            .map(|(index, (cell, _))| (*cell, index))
+            .collect();
+        Puzzle {
+            grid_map,
+            objectives,
+            objective_index,
+            objective_hits,
+            config,
+        }
+    }
+
+    /// It searches the states layer by layer, each layer uses one more bomb than the previous one
+    ///
+    /// Arguments:
+    ///
+    /// * `limit_bombs`: Whether the player can only use the starting bombs and the picked up ones
+    ///
+    /// Returns:
+    ///
+    /// SearchResult
+    fn search(&self, limit_bombs: bool) -> SearchResult {
+        let size = self.objectives.len();
+        let start = self.collect_items(SearchState {
+            removed: ObjectiveSet::new(size),
+            collected: ObjectiveSet::new(size),
+            damage: vec![0; size],
+        });
+        let mut visited: HashSet<SearchState> = HashSet::default();
+        visited.insert(start.clone());
+        let mut layer = vec![start];
+
+        let mut bombs_used = 0;
+        loop {
+            let mut next_layer = Vec::new();
+            for state in layer.iter() {
+                if state.removed.len() == size {
+                    return SearchResult::Found(bombs_used);
+                }
+                let mut bombs_left = self.config.bombs as i64
+                    + self.count_collected(state, UpgradeType::Bomb) as i64;
+                //Bombs are placed one at a time, capacity bombs are back before the next one
+                if self.config.bomb_rule == BombRule::Consumable {
+                    bombs_left -= bombs_used as i64;
+                }
+                if limit_bombs && bombs_left <= 0 {
+                    continue;
+                }
+                for next_state in self.place_bombs(state) {
+                    if visited.insert(next_state.clone()) {
+                        next_layer.push(next_state);
+                    }
+                }
+                if visited.len() > self.config.max_states {
+                    return SearchResult::LimitReached;
+                }
+            }
+            if next_layer.is_empty() {
+                return SearchResult::Exhausted;
+            }
+            layer = next_layer;
+            bombs_used += 1;
+        }
+    }
+
+    /// It returns the grid with the removed objectives turned into floor
+    fn grid_for(&self, state: &SearchState) -> GridMap {
+        let mut grid_map = self.grid_map.clone();
+        for (index, (cell, _)) in self.objectives.iter().enumerate() {
+            if state.removed.contains(index) {
+                grid_map.set(*cell, CellContent::Floor);
+            }
+        }
+        grid_map
+    }
+
+    /// It picks up every item the player can walk to
+    fn collect_items(&self, mut state: SearchState) -> SearchState {
+        let reachable = self
+            .grid_for(&state)
+            .flood_fill(self.config.spawn, |content| content.is_walkable());
+        for (index, (cell, content)) in self.objectives.iter().enumerate() {
+            if matches!(content, CellContent::Item(_))
+                && !state.removed.contains(index)
+                && reachable.contains(cell)
+            {
+                state.removed.insert(index);
+                state.collected.insert(index);
+            }
+        }
+        state
+    }
+
+    fn count_collected(&self, state: &SearchState, upgrade: UpgradeType) -> usize {
+        self.objectives
+            .iter()
+            .enumerate()
+            .filter(|(index, (_, content))| {
+                state.collected.contains(*index) && *content == CellContent::Item(upgrade)
+            })
+            .count()
+    }
+
+    /// It returns every state reached by placing one bomb on a cell the player can walk to
+    fn place_bombs(&self, state: &SearchState) -> Vec<SearchState> {
+        let grid_map = self.grid_for(state);
+        if !matches!(grid_map.get(self.config.spawn), Some(content) if content.is_walkable()) {
+            return Vec::new();
+        }
+        let range = GridMap::range_to_cells(
+            self.config.range
+                + self.count_collected(state, UpgradeType::Fire) as f32 * UPGRADE_FIRE_RANGE,
+        );
+        let mut reachable: Vec<IVec2> = grid_map
+            .flood_fill(self.config.spawn, |content| content.is_walkable())
+            .into_iter()
+            .collect();
+        reachable.sort_by_key(|cell| (cell.y, cell.x));
+
+        let mut seen_hits: HashSet<Vec<usize>> = HashSet::default();
+        let mut next_states = Vec::new();
+        for cell in reachable {
+            let mut hits: Vec<usize> = grid_map
+                .blast_cells(cell, range)
+                .iter()
+                .filter_map(|blast_cell| self.objective_index.get(blast_cell).copied())
+                .filter(|index| !state.removed.contains(*index))
+                .collect();
+            hits.sort_unstable();
+            if hits.is_empty() || !seen_hits.insert(hits.clone()) {
+                continue;
+            }
+            let mut next_state = state.clone();
+            for index in hits {
+                next_state.damage[index] += 1;
+                if next_state.damage[index] >= self.objective_hits[index] {
+                    next_state.removed.insert(index);
+                }
+            }
+            next_states.push(self.collect_items(next_state));
+        }
+        next_states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::MAP_PALETTE_PATH, map::palette::MapPalette};
+
+    fn solve_text(text: &str, bombs: u32, bomb_rule: BombRule) -> SolverResult {
+        let map = MapAsset::parse(text).unwrap();
+        let palette = MapPalette::from_file(MAP_PALETTE_PATH).unwrap();
+        let config = SolverConfig {
+            spawn: map.spawn_cell(),
+            bombs,
+            range: 1.0,
+            bomb_rule,
+            max_states: 10_000,
+        };
+        solve(&map, &palette, &config)
+    }
+
+    #[test]
+    fn one_breakable_needs_one_bomb() {
+        let map = "$$$$$\n$P @$\n$$$$$";
+        assert_eq!(
+            solve_text(map, 1, BombRule::Consumable),
+            SolverResult::Solvable { bombs: 1 }
+        );
+        assert_eq!(
+            solve_text(map, 0, BombRule::Consumable),
+            SolverResult::Unsolvable {
+                bombs_needed: Some(1)
+            }
+        );
+    }
+
+    #[test]
+    fn reinforced_breakable_needs_a_bomb_per_hit() {
+        let map = "$$$$$\n$P &$\n$$$$$";
+        assert_eq!(
+            solve_text(map, 3, BombRule::Consumable),
+            SolverResult::Solvable { bombs: 3 }
+        );
+        assert_eq!(
+            solve_text(map, 2, BombRule::Consumable),
+            SolverResult::Unsolvable {
+                bombs_needed: Some(3)
+            }
+        );
+        assert_eq!(
+            solve_text(map, 1, BombRule::Capacity),
+            SolverResult::Solvable { bombs: 3 }
+        );
+    }
+
+    #[test]
+    fn walled_breakable_is_unsolvable() {
+        let map = "$$$$$$\n$P $@$\n$$$$$$";
+        assert_eq!(
+            solve_text(map, 5, BombRule::Consumable),
+            SolverResult::Unsolvable { bombs_needed: None }
+        );
+    }
+
+    #[test]
+    fn picked_up_bombs_count() {
+        let map = "$$$$$$\n$PC @$\n$$$$$$";
+        assert_eq!(
+            solve_text(map, 0, BombRule::Consumable),
+            SolverResult::Solvable { bombs: 1 }
+        );
+    }
+}
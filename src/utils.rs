@@ -13,7 +13,11 @@ use rand::Rng;
 use crate::{
     collider::{InteractiveItem, UpgradeType},
     constants::DEFAULT_OBJECT_SCALE,
-    map::{AnimatedRotation, Breakable, CustomProps, ObjectProps},
+    map::{
+        grid::{CellContent, GridMap},
+        hidden::HiddenItem,
+        AnimatedRotation, Breakable, CustomProps, Health, ObjectProps,
+    },
 };
 
 #[derive(Component)]
@@ -90,7 +94,7 @@ pub fn spawn_object(
 /// * `object_props`: This is the ObjectProps struct that we defined earlier.
 /// * `asset_server`: The asset server that will load the object.
 /// * `translation`: Vec3 - The position of the object
-/// * `default_floor`: If true, the floor will be spawned with the default floor model.
+/// * `default_floor`: If set, the floor will be spawned with this model instead of the object one.
 ///
 /// Returns:
 ///
@@ -100,18 +104,23 @@ pub fn spawn_floor(
     object_props: &ObjectProps,
     asset_server: &AssetServer,
     translation: Vec3,
-    default_floor: bool,
+    default_floor: Option<&str>,
 ) -> Entity {
-    let path = if !default_floor {
-        object_props.path.clone().to_string()
-    } else {
-        "objects/tile.glb#Scene0".to_string()
+    let path = match default_floor {
+        Some(default_floor) => default_floor.to_string(),
+        None => object_props.path.clone().to_string(),
+    };
+    //Only the object floor is turned, the default floor is the same on every side
+    let rotation = match (&object_props.custom, default_floor) {
+        (Some(custom), None) => custom.rotation,
+        _ => Quat::IDENTITY,
     };
     commands
         .spawn(SceneBundle {
             scene: asset_server.load(path),
             transform: Transform {
                 translation: translation,
+                rotation,
                 scale: DEFAULT_OBJECT_SCALE.to_owned(),
                 ..Default::default()
             },
@@ -158,6 +167,7 @@ pub fn spawn_custom(
     if object_props.breakable {
         object_spawn
             .insert(Breakable)
+            .insert(Health::new(object_props))
             .insert(RigidBody::KinematicPositionBased);
     }
     if object_props.upgrade != UpgradeType::None {
@@ -181,6 +191,41 @@ pub fn spawn_custom(
         .id()
 }
 
+/// It spawns a decorative model without collider, it is cleaned up with the rest of the map
+///
+/// Arguments:
+///
+/// * `commands`: &mut Commands
+/// * `asset_server`: The asset server that will load the model
+/// * `path`: The path of the model
+/// * `translation`: The position of the model
+/// * `rotation`: The rotation around the Y axis, in radians
+///
+/// Returns:
+///
+/// Entity
+pub fn spawn_decoration(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    path: &str,
+    translation: Vec3,
+    rotation: f32,
+) -> Entity {
+    commands
+        .spawn(SceneBundle {
+            scene: asset_server.load(path.to_owned()),
+            transform: Transform {
+                translation,
+                rotation: Quat::from_rotation_y(rotation),
+                scale: DEFAULT_OBJECT_SCALE.to_owned(),
+            },
+            ..default()
+        })
+        .insert(MapObject)
+        .insert(Name::new("Decoration"))
+        .id()
+}
+
 //---------------------------Items Helpers--------------------------//
 
 /// "For each upgrade item, rotate it around the y axis."
@@ -205,6 +250,7 @@ pub fn animate_interactive_items(
 pub fn possibly_spawn_upgrade(
     commands: &mut Commands,
     asset_server: &AssetServer,
+    grid_map: &mut GridMap,
     translation: Vec3,
 ) {
     //Possibly spawn an item
@@ -212,42 +258,93 @@ pub fn possibly_spawn_upgrade(
     //Get a random value between 0 and 100
     let random_value = rand::thread_rng().gen_range(0..100);
 
-    let upgrade_to_spawn;
-    let upgrade_name;
     let upgrade_type;
 
     //Since 20% or 20 numbers between 0 and 100 are possible
     //we divide the 20 number into 4 options within a range of 5 numbers each
     if random_value >= 0 && random_value <= 5 {
-        upgrade_to_spawn = "objects/fireup.glb#Scene0";
-        upgrade_name = "FireUp";
         upgrade_type = UpgradeType::Fire;
     } else if random_value >= 5 && random_value <= 15 {
         //More chance to get bomb upgrade
-        upgrade_to_spawn = "objects/bombup.glb#Scene0";
-        upgrade_name = "BombUp";
         upgrade_type = UpgradeType::Bomb;
     } else {
-        upgrade_to_spawn = "objects/speedup.glb#Scene0";
-        upgrade_name = "SpeedUp";
         upgrade_type = UpgradeType::Speed;
     };
     if random_value >= 0 && random_value <= 20 {
-        let object_props = ObjectProps {
-            add_floor: true,
-            is_floor: false,
-            upgrade: upgrade_type,
-            path: upgrade_to_spawn.to_owned(),
-            custom: Some(CustomProps {
-                scale: Vec3::new(0.2, 0.3, 0.2),
-                rotation: Quat::from_rotation_y(0.0),
-                sum_translation: Vec3::ZERO,
-            }),
-            animated_rotation: true,
-            breakable: true,
-            name: String::from(upgrade_name),
-        };
-
-        let upgrade = spawn_custom(commands, &object_props, &asset_server, translation);
+        spawn_upgrade(commands, asset_server, grid_map, upgrade_type, translation);
+    }
+}
+
+/// It spawns what a destroyed breakable leaves behind. Breakables without a hidden item roll a
+/// random upgrade, hidden exits leave the floor for the flag
+///
+/// Arguments:
+///
+/// * `commands`: &mut Commands
+/// * `asset_server`: &AssetServer
+/// * `grid_map`: The logical map, the cell gets the spawned item
+/// * `hidden`: What the map hides under the breakable, if it hides something
+/// * `translation`: The position of the breakable
+pub fn spawn_hidden_item(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    grid_map: &mut GridMap,
+    hidden: Option<HiddenItem>,
+    translation: Vec3,
+) {
+    match hidden {
+        Some(HiddenItem::Upgrade(UpgradeType::None))
+        | Some(HiddenItem::Nothing)
+        | Some(HiddenItem::Exit) => {}
+        Some(HiddenItem::Upgrade(upgrade_type)) => {
+            spawn_upgrade(commands, asset_server, grid_map, upgrade_type, translation);
+        }
+        None => possibly_spawn_upgrade(commands, asset_server, grid_map, translation),
     }
 }
+
+/// It spawns an upgrade that the player picks up
+///
+/// Arguments:
+///
+/// * `commands`: &mut Commands
+/// * `asset_server`: &AssetServer
+/// * `grid_map`: The logical map, the cell gets the item
+/// * `upgrade_type`: The upgrade to spawn
+/// * `translation`: The position of the upgrade
+pub fn spawn_upgrade(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    grid_map: &mut GridMap,
+    upgrade_type: UpgradeType,
+    translation: Vec3,
+) {
+    let (upgrade_to_spawn, upgrade_name) = match upgrade_type {
+        UpgradeType::Fire => ("objects/fireup.glb#Scene0", "FireUp"),
+        UpgradeType::Bomb => ("objects/bombup.glb#Scene0", "BombUp"),
+        UpgradeType::Speed | UpgradeType::None => ("objects/speedup.glb#Scene0", "SpeedUp"),
+    };
+    let object_props = ObjectProps {
+        add_floor: true,
+        is_floor: false,
+        upgrade: upgrade_type,
+        path: upgrade_to_spawn.to_owned(),
+        custom: Some(CustomProps {
+            scale: Vec3::new(0.2, 0.3, 0.2),
+            rotation: Quat::from_rotation_y(0.0),
+            sum_translation: Vec3::ZERO,
+        }),
+        animated_rotation: true,
+        breakable: true,
+        name: String::from(upgrade_name),
+        autotile: None,
+        hits: None,
+        damaged_paths: Vec::new(),
+    };
+
+    spawn_custom(commands, &object_props, &asset_server, translation);
+    grid_map.set(
+        GridMap::world_to_cell(translation),
+        CellContent::Item(upgrade_type),
+    );
+}